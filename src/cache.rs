@@ -0,0 +1,168 @@
+use crate::clock::jitter;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// Bounded cache that evicts the least-recently-used entry when full, and
+/// optionally expires entries a fixed time after they were inserted
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    entries: HashMap<K, (V, Instant)>,
+    // Front is least recently used, back is most recently used
+    order: VecDeque<K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_ttl(capacity, None)
+    }
+
+    pub fn with_ttl(capacity: usize, ttl: Option<Duration>) -> Self {
+        LruCache {
+            capacity: capacity.max(1),
+            ttl,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Returns a copy of the cached value and marks it as recently used
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let (value, inserted) = self.entries.get(key)?;
+        if self.is_expired(*inserted, Instant::now()) {
+            self.remove(key);
+            return None;
+        }
+        let value = value.clone();
+        self.touch(key);
+        Some(value)
+    }
+
+    /// Drops every entry older than the TTL, returning how many were removed
+    pub fn remove_expired(&mut self, now: Instant) -> usize {
+        let expired: Vec<K> = self
+            .entries
+            .iter()
+            .filter(|(_, (_, inserted))| self.is_expired(*inserted, now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.remove(key);
+        }
+        expired.len()
+    }
+
+    /// Inserts or replaces a value, evicting the least-recently-used entry if needed
+    pub fn insert(&mut self, key: K, value: V) {
+        if self
+            .entries
+            .insert(key.clone(), (value, Instant::now()))
+            .is_some()
+        {
+            self.touch(&key);
+            return;
+        }
+        self.order.push_back(key);
+        while self.entries.len() > self.capacity {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn is_expired(&self, inserted: Instant, now: Instant) -> bool {
+        self.ttl
+            .is_some_and(|ttl| now.saturating_duration_since(inserted) >= ttl)
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.entries.remove(key);
+        self.order.retain(|k| k != key);
+    }
+
+    fn touch(&mut self, key: &K) {
+        if let Some(pos) = self.order.iter().position(|k| k == key)
+            && let Some(k) = self.order.remove(pos)
+        {
+            self.order.push_back(k);
+        }
+    }
+}
+
+/// Background task evicting expired cache entries; stopped when dropped
+#[derive(Debug)]
+pub struct CleanupTask(JoinHandle<()>);
+
+impl Drop for CleanupTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Periodically removes expired entries so idle caches don't hold stale data
+/// until their next lookup. Runs are jittered so several caches don't line up.
+pub fn spawn_cleanup<K, V>(cache: Arc<Mutex<LruCache<K, V>>>, interval: Duration) -> CleanupTask
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    CleanupTask(tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval + jitter(interval / 10)).await;
+            let removed = cache.lock().unwrap().remove_expired(Instant::now());
+            if removed > 0 {
+                debug!("Cache cleanup removed {} expired entries", removed);
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        // Touch "a" so "b" becomes the least recently used entry
+        assert_eq!(cache.get(&"a"), Some(1));
+        cache.insert("c", 3);
+
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+    }
+
+    #[test]
+    fn test_lru_replace_does_not_grow() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("a", 2);
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.get(&"a"), Some(2));
+    }
+
+    #[test]
+    fn test_cleanup_removes_expired_entries() {
+        let mut cache = LruCache::with_ttl(10, Some(Duration::from_secs(60)));
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        let now = Instant::now();
+        assert_eq!(cache.remove_expired(now), 0);
+
+        assert_eq!(cache.remove_expired(now + Duration::from_secs(61)), 2);
+        assert!(cache.entries.is_empty());
+        assert!(cache.order.is_empty());
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of the current time so date defaults can be tested deterministically
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Wall clock used outside of tests
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Pseudo-random delay below `max`, seeded from the clock's sub-second part. Good
+/// enough to keep periodic tasks and retries from several callers from lining up.
+pub fn jitter(max: Duration) -> Duration {
+    let max_ms = max.as_millis() as u64;
+    if max_ms == 0 {
+        return Duration::ZERO;
+    }
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or_default();
+    Duration::from_millis(seed % max_ms)
+}
+
+/// Clock frozen at a given instant
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_fixed_clock_is_frozen() {
+        let instant = Utc.with_ymd_and_hms(2025, 5, 1, 12, 0, 0).unwrap();
+        let clock = FixedClock(instant);
+        assert_eq!(clock.now(), instant);
+        assert_eq!(clock.now(), clock.now());
+    }
+
+    #[test]
+    fn test_jitter_is_bounded() {
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+        for _ in 0..100 {
+            assert!(jitter(Duration::from_millis(250)) < Duration::from_millis(250));
+        }
+    }
+}
@@ -0,0 +1,546 @@
+use std::env;
+use std::str::FromStr;
+use tracing::warn;
+
+const DEFAULT_LOCATION_CACHE_SIZE: usize = 1000;
+const DEFAULT_RESULT_CACHE_TTL_SECS: u64 = 300;
+const DEFAULT_MAX_REQUESTS_PER_SEARCH: usize = 20;
+const DEFAULT_CABIN_ORDER: &str = "economy,premium_economy,business,first";
+const DEFAULT_OTLP_SCHEME: &str = "http";
+const DEFAULT_SKYSCANNER_API_HOST: &str = "skyscanner89.p.rapidapi.com";
+const DEFAULT_CACHE_CLEANUP_INTERVAL_SECS: u64 = 300;
+const DEFAULT_MAX_RESULTS: usize = 5;
+const DEFAULT_BOOK_BY_LEAD_DAYS: u32 = 60;
+const DEFAULT_TIMEOUT_SECS: u64 = 15;
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_RETRY_BUDGET_MS: u64 = 10_000;
+const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 500;
+/// Bounds for the number of options returned by one search
+pub const MAX_RESULTS_RANGE: std::ops::RangeInclusive<usize> = 1..=50;
+
+/// Runtime settings resolved from the environment
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub rapidapi_key: Option<String>,
+    pub openai_api_key: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub service_name: Option<String>,
+    pub location_cache_size: usize,
+    /// Seconds a resolved location stays cached; unset keeps entries until evicted
+    pub location_cache_ttl_secs: Option<u64>,
+    /// How long a paged search's full result set is kept for the following pages;
+    /// zero searches again for every page
+    pub result_cache_ttl_secs: u64,
+    /// Seconds between background sweeps of expired cache entries
+    pub cache_cleanup_interval_secs: u64,
+    pub retry_on_empty: bool,
+    pub max_requests_per_search: usize,
+    /// Seconds before an outbound provider request is abandoned
+    pub request_timeout_secs: u64,
+    /// Retries of a rate-limited or failing provider request after the first attempt
+    pub max_retries: u32,
+    /// Total time all retries of one request may spend waiting, in milliseconds
+    pub retry_budget_ms: u64,
+    /// First backoff delay in milliseconds, doubled on each further retry
+    pub retry_base_delay_ms: u64,
+    /// Cabins from lowest to highest, used when stepping down for `fallback_cabin`
+    pub cabin_order: Vec<String>,
+    /// Options returned when the model doesn't ask for a specific number
+    pub default_max_results: usize,
+    /// Days before departure to recommend booking by, for the `book_by` option
+    pub book_by_lead_days: u32,
+    /// Level for logging outbound request parameters
+    pub log_requests: RequestLogLevel,
+    /// Largest share of unparseable itineraries tolerated before failing the search
+    pub max_degraded_results: Option<f64>,
+    /// Whether logs are also written to stdout alongside the OTEL exporters
+    pub stdout_logs: bool,
+    /// Use auto-complete entries that have an entity id but no sky id as a last resort
+    pub allow_entity_only_locations: bool,
+    /// Scheme prepended to an OTLP endpoint given without one
+    pub otlp_default_scheme: String,
+    /// Compression applied to OTLP exports
+    pub otlp_compression: OtlpCompression,
+    /// Headers sent with every OTLP export, e.g. auth tokens for hosted collectors
+    pub otlp_headers: Vec<(String, String)>,
+    /// RapidAPI host sent as `X-RapidAPI-Host`
+    pub skyscanner_api_host: String,
+    /// Root of the Skyscanner API, overridable to point at a mock server
+    pub skyscanner_base_url: String,
+    /// Treat a 404 from the Skyscanner list endpoint as a search with no flights
+    pub skyscanner_not_found_as_empty: bool,
+    /// Header carrying a hash of each request, for a caching proxy in front of RapidAPI
+    pub cache_key_header: Option<String>,
+    /// Upper case airport codes searches may start or end at; empty allows any
+    pub airport_allowlist: Vec<String>,
+    /// Upper case airport codes never searched from, to or through
+    pub airport_denylist: Vec<String>,
+    /// Settings that couldn't be read and fell back to their default, listed by `diagnostics`
+    pub warnings: Vec<String>,
+}
+
+/// How verbosely outbound request parameters are logged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestLogLevel {
+    Info,
+    Debug,
+    Off,
+}
+
+impl RequestLogLevel {
+    fn parse(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "debug" => RequestLogLevel::Debug,
+            "off" | "false" | "0" | "none" => RequestLogLevel::Off,
+            _ => RequestLogLevel::Info,
+        }
+    }
+}
+
+/// Compression for the OTLP exporters, from `OTEL_EXPORTER_OTLP_COMPRESSION`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OtlpCompression {
+    None,
+    Gzip,
+}
+
+impl OtlpCompression {
+    fn parse(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "gzip" => OtlpCompression::Gzip,
+            _ => OtlpCompression::None,
+        }
+    }
+}
+
+impl Default for Config {
+    /// Built-in defaults with no credentials, as if no variables were set
+    fn default() -> Self {
+        Config {
+            rapidapi_key: None,
+            openai_api_key: None,
+            otlp_endpoint: None,
+            service_name: None,
+            location_cache_size: DEFAULT_LOCATION_CACHE_SIZE,
+            location_cache_ttl_secs: None,
+            result_cache_ttl_secs: DEFAULT_RESULT_CACHE_TTL_SECS,
+            cache_cleanup_interval_secs: DEFAULT_CACHE_CLEANUP_INTERVAL_SECS,
+            retry_on_empty: false,
+            max_requests_per_search: DEFAULT_MAX_REQUESTS_PER_SEARCH,
+            request_timeout_secs: DEFAULT_TIMEOUT_SECS,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_budget_ms: DEFAULT_RETRY_BUDGET_MS,
+            retry_base_delay_ms: DEFAULT_RETRY_BASE_DELAY_MS,
+            cabin_order: DEFAULT_CABIN_ORDER.split(',').map(String::from).collect(),
+            default_max_results: DEFAULT_MAX_RESULTS,
+            book_by_lead_days: DEFAULT_BOOK_BY_LEAD_DAYS,
+            log_requests: RequestLogLevel::Info,
+            max_degraded_results: None,
+            stdout_logs: true,
+            allow_entity_only_locations: true,
+            otlp_default_scheme: DEFAULT_OTLP_SCHEME.to_string(),
+            otlp_compression: OtlpCompression::None,
+            otlp_headers: Vec::new(),
+            skyscanner_api_host: DEFAULT_SKYSCANNER_API_HOST.to_string(),
+            skyscanner_base_url: format!("https://{}", DEFAULT_SKYSCANNER_API_HOST),
+            skyscanner_not_found_as_empty: false,
+            cache_key_header: None,
+            airport_allowlist: Vec::new(),
+            airport_denylist: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let skyscanner_api_host = env_string("SKYSCANNER_API_HOST")
+            .map(|v| v.trim().to_string())
+            .unwrap_or(DEFAULT_SKYSCANNER_API_HOST.to_string());
+        let mut warnings = Vec::new();
+        let result_cache_ttl_secs =
+            result_cache_ttl_secs(env_string("FLIGHT_SEARCH_RESULT_CACHE_TTL_SECS"))
+                .unwrap_or_else(|warning| {
+                    warn!("{}", warning);
+                    warnings.push(warning);
+                    DEFAULT_RESULT_CACHE_TTL_SECS
+                });
+        Config {
+            rapidapi_key: env_string("RAPIDAPI_KEY"),
+            openai_api_key: env_string("OPENAI_API_KEY"),
+            otlp_endpoint: env_string("OTEL_EXPORTER_OTLP_ENDPOINT"),
+            service_name: env_string("OTEL_SERVICE_NAME"),
+            location_cache_size: env_positive(
+                "FLIGHT_SEARCH_LOCATION_CACHE_SIZE",
+                DEFAULT_LOCATION_CACHE_SIZE,
+            ),
+            location_cache_ttl_secs: env_string("FLIGHT_SEARCH_LOCATION_CACHE_TTL_SECS")
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .filter(|v| *v > 0),
+            result_cache_ttl_secs,
+            cache_cleanup_interval_secs: env_positive(
+                "FLIGHT_SEARCH_CACHE_CLEANUP_INTERVAL_SECS",
+                DEFAULT_CACHE_CLEANUP_INTERVAL_SECS,
+            ),
+            retry_on_empty: env_flag("FLIGHT_SEARCH_RETRY_ON_EMPTY", false),
+            max_requests_per_search: env_positive(
+                "FLIGHT_SEARCH_MAX_REQUESTS_PER_SEARCH",
+                DEFAULT_MAX_REQUESTS_PER_SEARCH,
+            ),
+            request_timeout_secs: env_positive("FLIGHT_SEARCH_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS),
+            // Zero is meaningful for both: no retries, or no time to wait for one
+            max_retries: env_string("FLIGHT_SEARCH_MAX_RETRIES")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_MAX_RETRIES),
+            retry_budget_ms: env_string("FLIGHT_SEARCH_RETRY_BUDGET_MS")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_RETRY_BUDGET_MS),
+            retry_base_delay_ms: env_positive(
+                "FLIGHT_SEARCH_RETRY_BASE_DELAY_MS",
+                DEFAULT_RETRY_BASE_DELAY_MS,
+            ),
+            cabin_order: env_list("FLIGHT_SEARCH_CABIN_ORDER", DEFAULT_CABIN_ORDER),
+            default_max_results: env_positive("FLIGHT_SEARCH_MAX_RESULTS", DEFAULT_MAX_RESULTS)
+                .clamp(*MAX_RESULTS_RANGE.start(), *MAX_RESULTS_RANGE.end()),
+            book_by_lead_days: env_positive(
+                "FLIGHT_SEARCH_BOOK_BY_LEAD_DAYS",
+                DEFAULT_BOOK_BY_LEAD_DAYS,
+            ),
+            log_requests: env_string("FLIGHT_SEARCH_LOG_REQUESTS")
+                .map(|v| RequestLogLevel::parse(&v))
+                .unwrap_or(RequestLogLevel::Info),
+            max_degraded_results: env_fraction("FLIGHT_SEARCH_MAX_DEGRADED_RESULTS"),
+            stdout_logs: env_flag("FLIGHT_SEARCH_STDOUT_LOGS", true),
+            allow_entity_only_locations: env_flag("FLIGHT_SEARCH_ALLOW_ENTITY_ONLY", true),
+            otlp_default_scheme: env_string("FLIGHT_SEARCH_OTLP_SCHEME")
+                .map(|v| v.trim().to_lowercase())
+                .unwrap_or(DEFAULT_OTLP_SCHEME.to_string()),
+            otlp_compression: env_string("OTEL_EXPORTER_OTLP_COMPRESSION")
+                .map(|v| OtlpCompression::parse(&v))
+                .unwrap_or(OtlpCompression::None),
+            otlp_headers: env_string("OTEL_EXPORTER_OTLP_HEADERS")
+                .map(|v| parse_headers(&v))
+                .unwrap_or_default(),
+            // Defaults to the API host, so switching RapidAPI providers only needs the host
+            skyscanner_base_url: env_string("SKYSCANNER_BASE_URL")
+                .map(|v| v.trim().trim_end_matches('/').to_string())
+                .unwrap_or_else(|| format!("https://{}", skyscanner_api_host)),
+            skyscanner_api_host,
+            skyscanner_not_found_as_empty: env_flag("SKYSCANNER_NOT_FOUND_AS_EMPTY", false),
+            cache_key_header: env_string("FLIGHT_SEARCH_CACHE_KEY_HEADER")
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            airport_allowlist: env_airports("FLIGHT_SEARCH_AIRPORT_ALLOWLIST"),
+            airport_denylist: env_airports("FLIGHT_SEARCH_AIRPORT_DENYLIST"),
+            warnings,
+        }
+    }
+
+    /// Human readable dump of the resolved configuration with secrets masked
+    pub fn diagnostics(&self, provider: &str, model: &str) -> String {
+        let exporter = match &self.otlp_endpoint {
+            Some(endpoint) => format!("otlp ({})", endpoint),
+            None => "stdout".to_string(),
+        };
+        let lines = [
+            format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            format!("provider: {}", provider),
+            format!("model: {}", model),
+            format!(
+                "RAPIDAPI_KEY: {}",
+                mask_secret(self.rapidapi_key.as_deref())
+            ),
+            format!(
+                "OPENAI_API_KEY: {}",
+                mask_secret(self.openai_api_key.as_deref())
+            ),
+            format!("otel traces/metrics/logs exporter: {}", exporter),
+            format!("FLIGHT_SEARCH_OTLP_SCHEME: {}", self.otlp_default_scheme),
+            format!(
+                "OTEL_EXPORTER_OTLP_COMPRESSION: {:?}",
+                self.otlp_compression
+            ),
+            format!(
+                "OTEL_EXPORTER_OTLP_HEADERS: {}",
+                header_names(&self.otlp_headers)
+            ),
+            format!(
+                "OTEL_SERVICE_NAME: {}",
+                self.service_name.as_deref().unwrap_or("(unset)")
+            ),
+            format!(
+                "FLIGHT_SEARCH_LOCATION_CACHE_SIZE: {}",
+                self.location_cache_size
+            ),
+            format!(
+                "FLIGHT_SEARCH_LOCATION_CACHE_TTL_SECS: {}",
+                self.location_cache_ttl_secs
+                    .map_or("(no expiry)".to_string(), |v| v.to_string())
+            ),
+            format!(
+                "FLIGHT_SEARCH_RESULT_CACHE_TTL_SECS: {}",
+                match self.result_cache_ttl_secs {
+                    0 => "(disabled)".to_string(),
+                    ttl => ttl.to_string(),
+                }
+            ),
+            format!(
+                "FLIGHT_SEARCH_CACHE_CLEANUP_INTERVAL_SECS: {}",
+                self.cache_cleanup_interval_secs
+            ),
+            format!("FLIGHT_SEARCH_RETRY_ON_EMPTY: {}", self.retry_on_empty),
+            format!(
+                "FLIGHT_SEARCH_MAX_REQUESTS_PER_SEARCH: {}",
+                self.max_requests_per_search
+            ),
+            format!("FLIGHT_SEARCH_TIMEOUT_SECS: {}", self.request_timeout_secs),
+            format!("FLIGHT_SEARCH_MAX_RETRIES: {}", self.max_retries),
+            format!("FLIGHT_SEARCH_RETRY_BUDGET_MS: {}", self.retry_budget_ms),
+            format!(
+                "FLIGHT_SEARCH_RETRY_BASE_DELAY_MS: {}",
+                self.retry_base_delay_ms
+            ),
+            format!("FLIGHT_SEARCH_CABIN_ORDER: {}", self.cabin_order.join(",")),
+            format!("FLIGHT_SEARCH_MAX_RESULTS: {}", self.default_max_results),
+            format!(
+                "FLIGHT_SEARCH_BOOK_BY_LEAD_DAYS: {}",
+                self.book_by_lead_days
+            ),
+            format!("FLIGHT_SEARCH_LOG_REQUESTS: {:?}", self.log_requests),
+            format!(
+                "FLIGHT_SEARCH_MAX_DEGRADED_RESULTS: {}",
+                self.max_degraded_results
+                    .map_or("(disabled)".to_string(), |v| v.to_string())
+            ),
+            format!("FLIGHT_SEARCH_STDOUT_LOGS: {}", self.stdout_logs),
+            format!(
+                "FLIGHT_SEARCH_ALLOW_ENTITY_ONLY: {}",
+                self.allow_entity_only_locations
+            ),
+            format!("SKYSCANNER_API_HOST: {}", self.skyscanner_api_host),
+            format!("SKYSCANNER_BASE_URL: {}", self.skyscanner_base_url),
+            format!(
+                "SKYSCANNER_NOT_FOUND_AS_EMPTY: {}",
+                self.skyscanner_not_found_as_empty
+            ),
+            format!(
+                "FLIGHT_SEARCH_CACHE_KEY_HEADER: {}",
+                self.cache_key_header.as_deref().unwrap_or("(unset)")
+            ),
+            format!(
+                "FLIGHT_SEARCH_AIRPORT_ALLOWLIST: {}",
+                airport_list(&self.airport_allowlist)
+            ),
+            format!(
+                "FLIGHT_SEARCH_AIRPORT_DENYLIST: {}",
+                airport_list(&self.airport_denylist)
+            ),
+        ];
+        let mut output = lines.join("\n");
+        for warning in &self.warnings {
+            output.push_str(&format!("\nwarning: {}", warning));
+        }
+        output
+    }
+}
+
+/// Reports whether a secret is present without revealing more than its last 4 characters
+fn mask_secret(secret: Option<&str>) -> String {
+    match secret {
+        None => "missing".to_string(),
+        Some(secret) if secret.chars().count() <= 8 => "present (****)".to_string(),
+        Some(secret) => {
+            let tail: String = secret.chars().skip(secret.chars().count() - 4).collect();
+            format!("present (****{})", tail)
+        }
+    }
+}
+
+/// Parses comma-separated `key=value` pairs, skipping entries without a key
+fn parse_headers(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim().to_lowercase(), value.trim().to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+/// Header names only, since values usually carry credentials
+fn header_names(headers: &[(String, String)]) -> String {
+    if headers.is_empty() {
+        return "(none)".to_string();
+    }
+    headers
+        .iter()
+        .map(|(key, _)| key.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Reads a variable, treating empty values as unset
+fn env_string(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
+
+/// Reads a number above zero, keeping `default` for zero or anything unparseable
+fn env_positive<T: FromStr + PartialOrd + Default>(name: &str, default: T) -> T {
+    env_string(name)
+        .and_then(|v| v.trim().parse::<T>().ok())
+        .filter(|v| *v > T::default())
+        .unwrap_or(default)
+}
+
+/// Unset keeps the default and zero turns result caching off. A value that doesn't
+/// parse is reported back so the caller can warn and keep the default.
+fn result_cache_ttl_secs(value: Option<String>) -> Result<u64, String> {
+    let Some(value) = value else {
+        return Ok(DEFAULT_RESULT_CACHE_TTL_SECS);
+    };
+    value.trim().parse().map_err(|_| {
+        format!(
+            "FLIGHT_SEARCH_RESULT_CACHE_TTL_SECS={:?} is not a number of seconds, using the default of {}",
+            value, DEFAULT_RESULT_CACHE_TTL_SECS
+        )
+    })
+}
+
+/// Reads a comma-separated list, ignoring blank entries
+fn env_list(name: &str, default: &str) -> Vec<String> {
+    env_string(name)
+        .unwrap_or_else(|| default.to_string())
+        .split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Reads a comma-separated list of airport codes, upper cased
+fn env_airports(name: &str) -> Vec<String> {
+    env_list(name, "")
+        .into_iter()
+        .map(|code| code.to_uppercase())
+        .collect()
+}
+
+fn airport_list(codes: &[String]) -> String {
+    if codes.is_empty() {
+        "(none)".to_string()
+    } else {
+        codes.join(",")
+    }
+}
+
+/// Reads a value between 0 and 1, ignoring anything else
+fn env_fraction(name: &str) -> Option<f64> {
+    env_string(name)
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|v| (0.0..=1.0).contains(v))
+}
+
+fn env_flag(name: &str, default: bool) -> bool {
+    env_string(name)
+        .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> Config {
+        Config {
+            rapidapi_key: Some("0123456789abcdef".to_string()),
+            otlp_endpoint: Some("http://localhost:4317".to_string()),
+            service_name: Some("flight_agent".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diagnostics_masks_api_key() {
+        let output = test_config().diagnostics("skyscanner", "gpt-4.1");
+        assert!(!output.contains("0123456789abcdef"));
+        assert!(output.contains("RAPIDAPI_KEY: present (****cdef)"));
+        assert!(output.contains("OPENAI_API_KEY: missing"));
+        assert!(output.contains("provider: skyscanner"));
+        assert!(output.contains("model: gpt-4.1"));
+        assert!(output.contains("exporter: otlp (http://localhost:4317)"));
+    }
+
+    #[test]
+    fn test_result_cache_ttl_secs() {
+        assert_eq!(
+            result_cache_ttl_secs(None),
+            Ok(DEFAULT_RESULT_CACHE_TTL_SECS)
+        );
+        assert_eq!(result_cache_ttl_secs(Some(" 60 ".to_string())), Ok(60));
+        assert_eq!(result_cache_ttl_secs(Some("0".to_string())), Ok(0));
+        let warning = result_cache_ttl_secs(Some("5m".to_string())).unwrap_err();
+        assert_eq!(
+            warning,
+            "FLIGHT_SEARCH_RESULT_CACHE_TTL_SECS=\"5m\" is not a number of seconds, using the default of 300"
+        );
+        let config = Config {
+            result_cache_ttl_secs: 0,
+            ..test_config()
+        };
+        assert!(
+            config
+                .diagnostics("skyscanner", "gpt-4.1")
+                .contains("FLIGHT_SEARCH_RESULT_CACHE_TTL_SECS: (disabled)")
+        );
+        // A value that didn't parse keeps the default and shows up in the diagnostics
+        let config = Config {
+            warnings: vec![warning],
+            ..test_config()
+        };
+        let output = config.diagnostics("skyscanner", "gpt-4.1");
+        assert!(output.contains("FLIGHT_SEARCH_RESULT_CACHE_TTL_SECS: 300"));
+        assert!(output.ends_with(
+            "\nwarning: FLIGHT_SEARCH_RESULT_CACHE_TTL_SECS=\"5m\" is not a number of seconds, using the default of 300"
+        ));
+    }
+
+    #[test]
+    fn test_request_log_level_parse() {
+        assert_eq!(RequestLogLevel::parse("debug"), RequestLogLevel::Debug);
+        assert_eq!(RequestLogLevel::parse("OFF"), RequestLogLevel::Off);
+        assert_eq!(RequestLogLevel::parse("info"), RequestLogLevel::Info);
+        assert_eq!(RequestLogLevel::parse("bogus"), RequestLogLevel::Info);
+    }
+
+    #[test]
+    fn test_otlp_compression_parse() {
+        assert_eq!(OtlpCompression::parse("gzip"), OtlpCompression::Gzip);
+        assert_eq!(OtlpCompression::parse(" GZIP "), OtlpCompression::Gzip);
+        assert_eq!(OtlpCompression::parse("none"), OtlpCompression::None);
+        assert_eq!(OtlpCompression::parse("zstd"), OtlpCompression::None);
+        assert_eq!(Config::default().otlp_compression, OtlpCompression::None);
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        assert_eq!(
+            parse_headers("Authorization=Bearer abc123, x-team = flights,broken,=orphan"),
+            vec![
+                ("authorization".to_string(), "Bearer abc123".to_string()),
+                ("x-team".to_string(), "flights".to_string()),
+            ]
+        );
+        assert!(parse_headers("").is_empty());
+
+        let config = Config {
+            otlp_headers: parse_headers("authorization=Bearer abc123"),
+            ..test_config()
+        };
+        let output = config.diagnostics("skyscanner", "gpt-4.1");
+        assert!(output.contains("OTEL_EXPORTER_OTLP_HEADERS: authorization"));
+        assert!(!output.contains("abc123"));
+    }
+
+    #[test]
+    fn test_mask_short_secret() {
+        assert_eq!(mask_secret(Some("abc")), "present (****)");
+    }
+}
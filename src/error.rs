@@ -6,10 +6,76 @@ pub enum FlightSearchError {
     HttpRequestFailed(String),
     #[error("Invalid response: {0}")]
     InvalidResponse(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
     #[error("API error: {0}")]
     ApiError(String),
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+    #[error("Request timed out: {0}")]
+    Timeout(String),
     #[error("Missing API key")]
     MissingApiKey,
+    #[error("Request limit exceeded: more than {0} requests for one search")]
+    RequestLimitExceeded(usize),
+    #[error("Response schema mismatch: {0}")]
+    SchemaMismatch(String),
+    #[error(
+        "Party of {requested} exceeds the provider limit of {max} passengers per booking; search for smaller groups separately"
+    )]
+    PartyTooLarge { requested: u32, max: u32 },
+    #[error("Current provider ({provider}) doesn't support {feature}")]
+    UnsupportedFeature {
+        provider: &'static str,
+        feature: &'static str,
+    },
+}
+
+impl FlightSearchError {
+    /// Stable identifier for programmatic consumers and structured logs
+    pub fn code(&self) -> &'static str {
+        match self {
+            FlightSearchError::HttpRequestFailed(_) => "ERR_HTTP",
+            FlightSearchError::InvalidResponse(_) => "ERR_INVALID_RESPONSE",
+            FlightSearchError::InvalidArgument(_) => "ERR_INVALID_ARGUMENT",
+            FlightSearchError::ApiError(_) => "ERR_API",
+            FlightSearchError::RateLimited(_) => "ERR_RATE_LIMITED",
+            FlightSearchError::Timeout(_) => "ERR_TIMEOUT",
+            FlightSearchError::MissingApiKey => "ERR_MISSING_KEY",
+            FlightSearchError::RequestLimitExceeded(_) => "ERR_REQUEST_LIMIT",
+            FlightSearchError::SchemaMismatch(_) => "ERR_SCHEMA_MISMATCH",
+            FlightSearchError::PartyTooLarge { .. } => "ERR_PARTY_TOO_LARGE",
+            FlightSearchError::UnsupportedFeature { .. } => "ERR_UNSUPPORTED_FEATURE",
+        }
+    }
+
+    /// Prefixes the message with what was being done, keeping the variant and code
+    pub fn context(self, context: &str) -> Self {
+        match self {
+            FlightSearchError::HttpRequestFailed(msg) => {
+                FlightSearchError::HttpRequestFailed(format!("{}: {}", context, msg))
+            }
+            FlightSearchError::InvalidResponse(msg) => {
+                FlightSearchError::InvalidResponse(format!("{}: {}", context, msg))
+            }
+            FlightSearchError::InvalidArgument(msg) => {
+                FlightSearchError::InvalidArgument(format!("{}: {}", context, msg))
+            }
+            FlightSearchError::ApiError(msg) => {
+                FlightSearchError::ApiError(format!("{}: {}", context, msg))
+            }
+            FlightSearchError::RateLimited(msg) => {
+                FlightSearchError::RateLimited(format!("{}: {}", context, msg))
+            }
+            FlightSearchError::Timeout(msg) => {
+                FlightSearchError::Timeout(format!("{}: {}", context, msg))
+            }
+            FlightSearchError::SchemaMismatch(msg) => {
+                FlightSearchError::SchemaMismatch(format!("{}: {}", context, msg))
+            }
+            other => other,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -30,10 +96,94 @@ mod tests {
             "Invalid response: malformed JSON"
         );
 
+        let invalid_argument = FlightSearchError::InvalidArgument("unknown sort".to_string());
+        assert_eq!(
+            invalid_argument.to_string(),
+            "Invalid argument: unknown sort"
+        );
+
         let api_error = FlightSearchError::ApiError("rate limit exceeded".to_string());
         assert_eq!(api_error.to_string(), "API error: rate limit exceeded");
 
         let missing_key = FlightSearchError::MissingApiKey;
         assert_eq!(missing_key.to_string(), "Missing API key");
+
+        let request_limit = FlightSearchError::RequestLimitExceeded(20);
+        assert_eq!(
+            request_limit.to_string(),
+            "Request limit exceeded: more than 20 requests for one search"
+        );
+
+        let unsupported = FlightSearchError::UnsupportedFeature {
+            provider: "skyscanner",
+            feature: "price_calendar",
+        };
+        assert_eq!(
+            unsupported.to_string(),
+            "Current provider (skyscanner) doesn't support price_calendar"
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let cases = [
+            (
+                FlightSearchError::HttpRequestFailed(String::new()),
+                "ERR_HTTP",
+            ),
+            (
+                FlightSearchError::InvalidResponse(String::new()),
+                "ERR_INVALID_RESPONSE",
+            ),
+            (
+                FlightSearchError::InvalidArgument(String::new()),
+                "ERR_INVALID_ARGUMENT",
+            ),
+            (FlightSearchError::ApiError(String::new()), "ERR_API"),
+            (
+                FlightSearchError::RateLimited(String::new()),
+                "ERR_RATE_LIMITED",
+            ),
+            (FlightSearchError::Timeout(String::new()), "ERR_TIMEOUT"),
+            (FlightSearchError::MissingApiKey, "ERR_MISSING_KEY"),
+            (
+                FlightSearchError::RequestLimitExceeded(20),
+                "ERR_REQUEST_LIMIT",
+            ),
+            (
+                FlightSearchError::SchemaMismatch(String::new()),
+                "ERR_SCHEMA_MISMATCH",
+            ),
+            (
+                FlightSearchError::PartyTooLarge {
+                    requested: 12,
+                    max: 9,
+                },
+                "ERR_PARTY_TOO_LARGE",
+            ),
+            (
+                FlightSearchError::UnsupportedFeature {
+                    provider: "skyscanner",
+                    feature: "price_calendar",
+                },
+                "ERR_UNSUPPORTED_FEATURE",
+            ),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.code(), code);
+        }
+    }
+
+    #[test]
+    fn test_error_context() {
+        let err = FlightSearchError::ApiError("Status: 429".to_string())
+            .context("Resolving destination 'Paris'");
+        assert_eq!(err.code(), "ERR_API");
+        assert_eq!(
+            err.to_string(),
+            "API error: Resolving destination 'Paris': Status: 429"
+        );
+        let err = FlightSearchError::MissingApiKey.context("Resolving source 'AUS'");
+        assert_eq!(err.to_string(), "Missing API key");
     }
 }
@@ -1,19 +1,64 @@
+use crate::cache::{CleanupTask, LruCache, spawn_cleanup};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Config, MAX_RESULTS_RANGE};
 use crate::error::FlightSearchError;
-use crate::metrics::{inc_flight_status_error, inc_flight_status_success};
-use chrono::{Duration, NaiveDate, Utc};
+use crate::geo::{Coordinates, format_distance};
+use crate::i18n::{OutputLabels, labels_for_locale};
+use crate::itinerary::ListResponse;
+use crate::metrics::{
+    elapsed_ms, inc_flight_search_failure, inc_flight_status_success,
+    record_flight_search_duration, record_resolve_duration, track_in_flight,
+};
+use crate::provider::{
+    FlightProvider, Location, ProviderCapabilities, ProviderResults, RequestBudget, SearchParams,
+    SkyscannerProvider, TripType, skyscanner_results,
+};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use futures::future::{join_all, try_join_all};
+use futures::{StreamExt, TryStreamExt, stream};
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::{Value, json};
-use std::collections::HashMap;
-use std::env;
-use tracing::{debug, error, info, instrument};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration as StdDuration, Instant};
+use tracing::{debug, error, info, instrument, warn};
 
 const DATE_FORMAT: &str = "%Y-%m-%d";
+/// Widest window on either side of the departure date for a price calendar
+const MAX_CALENDAR_DAYS: u32 = 7;
+/// Concurrent searches while building a price calendar
+const CALENDAR_CONCURRENCY: usize = 3;
+/// Days on either side of the departure probed for `suggest_cheaper_dates`
+const CHEAPER_DATE_PROBE_DAYS: u32 = 2;
+/// Share of the fare a nearby date must save before it's worth suggesting
+const CHEAPER_DATE_MIN_SAVING: f64 = 0.1;
+/// Minimum days out for each entry of `OutputLabels::lead_time_advice`, longest horizon first
+const LEAD_TIME_HORIZONS: [i64; 4] = [120, 21, 7, 0];
+/// Consistent (region, market, currency, locale) defaults
+const REGION_PROFILES: &[(&str, &str, &str, &str)] = &[
+    ("en-US", "US", "USD", "en-US"),
+    ("en-GB", "UK", "GBP", "en-GB"),
+    ("en-CA", "CA", "CAD", "en-CA"),
+    ("en-AU", "AU", "AUD", "en-AU"),
+    ("en-IN", "IN", "INR", "en-IN"),
+    ("es-ES", "ES", "EUR", "es-ES"),
+    ("es-MX", "MX", "MXN", "es-MX"),
+    ("fr-FR", "FR", "EUR", "fr-FR"),
+    ("de-DE", "DE", "EUR", "de-DE"),
+    ("it-IT", "IT", "EUR", "it-IT"),
+    ("pt-PT", "PT", "EUR", "pt-PT"),
+    ("pt-BR", "BR", "BRL", "pt-BR"),
+    ("ja-JP", "JP", "JPY", "ja-JP"),
+];
+/// Paged searches whose full result sets are kept at once
+const RESULT_CACHE_SIZE: usize = 100;
+const EMPTY_RETRY_DELAY: StdDuration = StdDuration::from_millis(1500);
 
 /// API parameters provided by model
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, Serialize, Default)]
 pub struct FlightSearchArgs {
     source: String,
     destination: String,
@@ -21,29 +66,809 @@ pub struct FlightSearchArgs {
     return_date: Option<String>,
     service: Option<String>,
     adults: Option<u8>,
+    children: Option<u8>,
+    infants: Option<u8>,
     currency: Option<String>,
+    exclude_self_transfer: Option<bool>,
+    currencies: Option<Vec<String>>,
+    booking_advice: Option<bool>,
+    response_format: Option<String>,
+    show_nearby_prices: Option<bool>,
+    suggest_cheaper_dates: Option<bool>,
+    region: Option<String>,
+    market: Option<String>,
+    locale: Option<String>,
+    sort_by: Option<String>,
+    price_calendar: Option<PriceCalendarWindow>,
+    fallback_cabin: Option<bool>,
+    max_quote_age: Option<u64>,
+    one_per_airline: Option<bool>,
+    via: Option<String>,
+    fare_breakdown: Option<bool>,
+    compare_cabins: Option<bool>,
+    premium_tolerance: Option<f64>,
+    include_timing: Option<bool>,
+    show_weekdays: Option<bool>,
+    group_by_stops: Option<bool>,
+    trip_type: Option<String>,
+    max_results: Option<i64>,
+    book_by: Option<bool>,
+    strict_cabin: Option<bool>,
+    show_distance: Option<bool>,
+    max_price: Option<f64>,
+    nonstop_only: Option<bool>,
+    max_stops: Option<usize>,
+    refundable_only: Option<bool>,
+    keep_unknown_refundability: Option<bool>,
+    explain: Option<bool>,
+    min_agent_rating: Option<f64>,
+    prefer_rated_agents: Option<bool>,
+}
+
+/// Days around the departure date covered by a price calendar
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy)]
+pub struct PriceCalendarWindow {
+    #[serde(default)]
+    days_before: u32,
+    #[serde(default)]
+    days_after: u32,
+}
+
+/// Base fare plus taxes and carrier-imposed fees, summing to roughly the total price
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FareBreakdown {
+    pub base: f64,
+    pub taxes: f64,
+    pub carrier_fees: f64,
 }
 
 /// Structured response provided to model
+#[derive(Debug, Serialize, Clone)]
 pub struct FlightOption {
     pub airline: String,
     pub flight_number: String,
+    /// Flight numbers of every segment of the first leg, in order
+    pub flight_numbers: Vec<String>,
     pub departure: String,
     pub arrival: String,
     pub duration: String,
+    pub duration_minutes: Option<u64>,
     pub stops: usize,
     pub price: f64,
+    /// Price divided by flight hours across every leg, absent when a duration is unknown
+    pub price_per_hour: Option<f64>,
+    /// Price divided by the great-circle distance of every leg, only set when `show_distance` is requested
+    pub price_per_km: Option<f64>,
     pub currency: String,
+    pub self_transfer: bool,
+    /// Fee for a checked bag when it is not included in the fare
+    pub baggage_fee: Option<f64>,
+    /// Authoritative fares keyed by currency code when several currencies were requested
+    pub prices: HashMap<String, f64>,
+    /// Age of the cached fare quote in seconds, when the provider reports it
+    pub quote_age_seconds: Option<u64>,
+    /// Whether the fare can be refunded, when the provider reports it
+    pub refundable: Option<bool>,
+    /// Operating carrier's number on codeshares, when it differs from the marketing one
+    pub operating_flight_number: Option<String>,
+    /// Number of segments in the first leg; more precise than `stops` for connections
+    pub segment_count: usize,
+    /// Airports where the first leg connects, in travel order
+    pub connection_airports: Vec<String>,
+    /// Component costs of the fare, only included in JSON when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fare_breakdown: Option<FareBreakdown>,
+    /// Airport codes the first leg actually departs from and arrives at, which can
+    /// differ from the requested ones on metro-area or nearby searches
+    pub origin_airport: String,
+    pub destination_airport: String,
+    /// Booking link of the first pricing option, when the provider returns one
+    pub booking_url: Option<String>,
+    /// Customer rating of the agent selling that fare, when the provider reports it
+    pub agent_rating: Option<f64>,
+    /// Quoted in a currency other than the requested one, so not comparable by price
+    pub currency_mismatch: bool,
+    /// Minimum connection time the provider assumed when building the itinerary
+    pub min_connection_minutes: Option<u64>,
+    /// Cabin class of every segment across all legs, lower case, where the provider reports it
+    pub segment_cabins: Vec<String>,
+    /// Distinct cabin classes of the outbound leg, lower case, where the provider reports them
+    pub cabins: Vec<String>,
+    /// Inbound leg of a roundtrip itinerary
+    pub return_leg: Option<LegDetails>,
+    /// Whether the price came from a finished search or may still change
+    pub price_confidence: PriceConfidence,
+    /// Why the option ranks where it does, only set when `explain` is requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank_reason: Option<String>,
+}
+
+/// How settled a quoted price is
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceConfidence {
+    /// The provider finished collecting prices
+    #[default]
+    Final,
+    /// The provider was still collecting prices; re-confirm before booking
+    Preliminary,
+}
+
+impl PriceConfidence {
+    fn from_complete(results_complete: bool) -> Self {
+        if results_complete {
+            PriceConfidence::Final
+        } else {
+            PriceConfidence::Preliminary
+        }
+    }
+}
+
+/// Carrier, times, duration and stops of a single leg
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct LegDetails {
+    pub airline: String,
+    pub flight_numbers: Vec<String>,
+    pub departure: String,
+    pub arrival: String,
+    pub duration: String,
+    pub duration_minutes: Option<u64>,
+    pub stops: usize,
+    /// Distinct cabin classes of the leg's segments, lower case
+    pub cabins: Vec<String>,
+    /// Airports where the leg connects, in travel order
+    pub connection_airports: Vec<String>,
+}
+
+impl FlightOption {
+    /// Flight numbers and times of every leg, identifying the itinerary whatever it costs
+    fn itinerary_key(&self) -> String {
+        let outbound = format!(
+            "{}|{}|{}",
+            self.flight_numbers.join(","),
+            self.departure,
+            self.arrival
+        );
+        match &self.return_leg {
+            Some(leg) => format!(
+                "{};{}|{}|{}",
+                outbound,
+                leg.flight_numbers.join(","),
+                leg.departure,
+                leg.arrival
+            ),
+            None => outbound,
+        }
+    }
+
+    /// Canonical key over every leg's flight numbers and times and the price (to the
+    /// cent) for dedup and change detection. FNV-1a keeps it stable across runs and
+    /// Rust releases.
+    pub fn signature(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+        let price_cents = (self.price * 100.0).round() as i64;
+        let key = format!("{}|{}|{}", self.itinerary_key(), price_cents, self.currency);
+        key.bytes().fold(FNV_OFFSET, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+}
+
+/// Parsed search results, serialized as-is for JSON output
+#[derive(Debug, Serialize, Default)]
+pub struct FlightSearchResult {
+    pub options: Vec<FlightOption>,
+    /// False while the provider is still collecting prices (`context.status` is incomplete)
+    pub results_complete: bool,
+    /// Itineraries the provider matched before filtering and truncation, when it reports a count
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_results: Option<u64>,
+    /// Cheapest fare per departure date, `None` when a date had no flights
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_calendar: Option<BTreeMap<String, Option<f64>>>,
+    /// Lower cabin searched because the requested one had no fares
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_cabin: Option<String>,
+    /// Time from the start of the tool call until the result was assembled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_latency_ms: Option<u64>,
+    /// Great-circle distance between the searched origin and destination
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+    /// Position of the resolved origin, for plotting the route
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin_coordinates: Option<Coordinates>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination_coordinates: Option<Coordinates>,
+    /// Cheapest fare of the searched cabin followed by each higher cabin worth showing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cabin_comparison: Option<Vec<CabinPrice>>,
+    /// Cheapest fares one day either side of the searched dates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nearby_prices: Option<NearbyPrices>,
+    /// Nearby departure date notably cheaper than the searched one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cheaper_date: Option<CheaperDate>,
+    /// Days between the search and departure, set when booking advice is requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days_to_departure: Option<i64>,
+    /// Recommended booking deadline, set when requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub book_by: Option<NaiveDate>,
+    /// Options are ordered in sections by number of stops rather than one ranking
+    pub grouped_by_stops: bool,
+    /// The search as it was run, after defaults were filled in and places resolved
+    pub query_echo: QueryEcho,
 }
 
+/// Search parameters a result was produced from
 #[derive(Debug, Serialize, Default)]
-struct SkyscannerLocation {
-    sky_id: String,
-    entity_id: String,
+pub struct QueryEcho {
+    /// Origin and destination as given by the caller
+    pub source: String,
+    pub destination: String,
+    pub resolved_source: Location,
+    pub resolved_destination: Location,
+    pub trip_type: TripType,
+    pub departure_date: String,
+    pub return_date: Option<String>,
+    pub cabin: String,
+    pub adults: u8,
+    pub children: u8,
+    pub infants: u8,
+    pub market: String,
+    pub currency: String,
+    pub locale: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_price: Option<f64>,
+    /// Today's date (UTC) when the search ran
+    pub searched_on: NaiveDate,
+}
+
+/// Cheapest fares on the days before and after the searched dates
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct NearbyPrices {
+    pub day_before: Option<f64>,
+    pub day_after: Option<f64>,
+}
+
+/// A cheaper departure date near the searched one
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct CheaperDate {
+    pub date: NaiveDate,
+    /// Days from the searched departure, negative when earlier
+    pub days_offset: i64,
+    pub price: f64,
+    /// Amount saved against the cheapest fare on the searched date
+    pub saving: f64,
+}
+
+impl FlightSearchResult {
+    /// Renders the result as the markdown the tool returns to the LLM, in the
+    /// searched locale, optionally opening with the travel dates and their weekdays
+    pub fn to_markdown(&self, show_weekdays: bool) -> String {
+        let query = &self.query_echo;
+        let labels = labels_for_locale(&query.locale);
+        if self.options.is_empty() {
+            if let Some(max_price) = query.max_price {
+                return format!(
+                    "{} {} {}",
+                    labels.no_flights_under, max_price, query.currency
+                );
+            }
+            return labels.no_flights.to_string();
+        }
+        let mut output = String::new();
+        if show_weekdays {
+            output.push_str(&format!(
+                "{}\n\n",
+                trip_dates_line(&query.departure_date, query.return_date.as_deref(), labels)
+            ));
+        }
+        if let Some(distance) = self.distance_km {
+            output.push_str(&format!(
+                "{}: {}\n\n",
+                labels.distance,
+                format_distance(distance)
+            ));
+        }
+        if self.grouped_by_stops {
+            output.push_str(&format_grouped_by_stops(&self.options, labels));
+        } else {
+            output.push_str(&format_flight_options(&self.options, labels));
+        }
+        if let Some(total) = self.total_results {
+            output.push_str(&format!(
+                "\n{}\n",
+                results_count_line(total, self.options.len(), labels)
+            ));
+        }
+        if !self.results_complete {
+            output.push_str(&format!("\n{}\n", labels.preliminary));
+        }
+        if let Some(cabin) = &self.fallback_cabin {
+            output.push_str(&format!("\n{} {}\n", labels.cabin_fallback, cabin));
+        }
+        if let Some(note) = metro_note(
+            &[&query.resolved_source, &query.resolved_destination],
+            labels,
+        ) {
+            output.push_str(&format!("\n{}\n", note));
+        }
+        if let Some(days_out) = self.days_to_departure {
+            output.push_str(&format!("\n{}\n", lead_time_advice(days_out, labels)));
+        }
+        if let Some(date) = self.book_by {
+            output.push_str(&format!(
+                "\n{}\n",
+                book_by_line(date, query.searched_on, labels)
+            ));
+        }
+        if let Some(comparison) = &self.cabin_comparison {
+            output.push_str(&format!(
+                "\n{}",
+                format_cabin_comparison(comparison, &query.currency, labels)
+            ));
+        }
+        if let Some(calendar) = &self.price_calendar {
+            output.push_str(&format!(
+                "\n{}",
+                format_price_calendar(calendar, &query.departure_date, &query.currency, labels)
+            ));
+        }
+        if let Some(nearby) = self.nearby_prices {
+            output.push_str(&format!(
+                "\n{}\n",
+                nearby_price_line(
+                    &query.currency,
+                    nearby.day_before,
+                    cheapest_price(self),
+                    nearby.day_after,
+                    labels
+                )
+            ));
+        }
+        if let Some(cheaper) = &self.cheaper_date {
+            output.push_str(&format!(
+                "\n{}\n",
+                cheaper_date_line(cheaper, &query.currency, labels)
+            ));
+        }
+        output
+    }
+}
+
+/// Cheapest fare found in one cabin
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct CabinPrice {
+    pub cabin: String,
+    pub price: Option<f64>,
+    /// The cabin's search failed, so it is listed without a price
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub unavailable: bool,
+}
+
+/// One page of a search's options and the size of the whole set
+#[derive(Debug, Serialize)]
+pub struct ResultPage {
+    pub results: Vec<FlightOption>,
+    /// 1-based page number
+    pub page: usize,
+    pub page_size: usize,
+    /// Options across all pages
+    pub total: usize,
 }
 
 #[derive(Debug)]
-pub struct FlightSearchTool;
+pub struct FlightSearchTool {
+    provider: Box<dyn FlightProvider>,
+    config: Config,
+    clock: Box<dyn Clock>,
+    /// Resolved locations, sized and expired per the tool's config
+    location_cache: LocationCache,
+    /// Full result sets of paged searches, keyed by provider and arguments
+    result_cache: Mutex<LruCache<String, Arc<FlightSearchResult>>>,
+}
+
+type LocationCache = Arc<Mutex<LruCache<String, Location>>>;
+
+impl FlightSearchTool {
+    /// Searches through the Skyscanner provider configured from the environment
+    pub fn new() -> Self {
+        let config = Config::from_env();
+        Self::with_provider(Box::new(SkyscannerProvider::from_config(&config)), config)
+    }
+
+    /// Searches through any provider, e.g. a fake in tests or an embedder's own backend
+    pub fn with_provider(provider: Box<dyn FlightProvider>, config: Config) -> Self {
+        let ttl = StdDuration::from_secs(config.result_cache_ttl_secs);
+        FlightSearchTool {
+            provider,
+            location_cache: location_cache(&config),
+            result_cache: Mutex::new(LruCache::with_ttl(RESULT_CACHE_SIZE, Some(ttl))),
+            config,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Starts sweeping expired locations in the background; `None` when entries don't
+    /// expire. Keep the returned task alive for as long as the tool is in use.
+    pub fn spawn_location_cache_cleanup(&self) -> Option<CleanupTask> {
+        self.config.location_cache_ttl_secs.map(|_| {
+            spawn_cleanup(
+                Arc::clone(&self.location_cache),
+                StdDuration::from_secs(self.config.cache_cleanup_interval_secs),
+            )
+        })
+    }
+
+    /// Uses `clock` for today's date instead of the system clock
+    pub fn with_clock(mut self, clock: Box<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns one 1-based page of a search's options. The full option set is cached
+    /// for `result_cache_ttl_secs`, so later pages of the same search don't search again.
+    pub async fn search_page(
+        &self,
+        mut args: FlightSearchArgs,
+        page: usize,
+        page_size: usize,
+    ) -> Result<ResultPage, FlightSearchError> {
+        if page == 0 || page_size == 0 {
+            return Err(FlightSearchError::InvalidArgument(format!(
+                "page and page_size start at 1, got page {} of size {}",
+                page, page_size
+            )));
+        }
+        // Page over as many options as one search returns
+        args.max_results = Some(*MAX_RESULTS_RANGE.end() as i64);
+        let key = serde_json::to_string(&args)
+            .map(|args| format!("{}:{}", self.provider.name(), args))
+            .map_err(|e| FlightSearchError::InvalidResponse(e.to_string()))?;
+        let cached = self.result_cache.lock().unwrap().get(&key);
+        let result = match cached {
+            Some(result) => result,
+            None => {
+                let result = Arc::new(self.search_structured(args).await?);
+                self.result_cache
+                    .lock()
+                    .unwrap()
+                    .insert(key, Arc::clone(&result));
+                result
+            }
+        };
+        Ok(ResultPage {
+            results: result
+                .options
+                .iter()
+                .skip((page - 1) * page_size)
+                .take(page_size)
+                .cloned()
+                .collect(),
+            page,
+            page_size,
+            total: result.options.len(),
+        })
+    }
+
+    /// Runs a search and returns the typed result, for callers that emit JSON or
+    /// render it themselves instead of taking the tool's markdown
+    #[instrument(name = "search_flights_structured", skip(self))]
+    pub async fn search_structured(
+        &self,
+        args: FlightSearchArgs,
+    ) -> Result<FlightSearchResult, FlightSearchError> {
+        // Counted once per search, however many provider requests it made
+        let mut route = None;
+        let result = self.run_search(args, &mut route).await;
+        match &result {
+            Ok(_) => inc_flight_status_success(),
+            Err(e) => inc_flight_search_failure(
+                e,
+                route
+                    .as_ref()
+                    .map(|(origin, destination)| (origin.as_str(), destination.as_str())),
+            ),
+        }
+        result
+    }
+
+    /// Runs one search, setting `route` to the resolved place ids once both are known
+    async fn run_search(
+        &self,
+        args: FlightSearchArgs,
+        route: &mut Option<(String, String)>,
+    ) -> Result<FlightSearchResult, FlightSearchError> {
+        let started = Instant::now();
+        let config = &self.config;
+        let provider = self.provider.as_ref();
+        let clock = self.clock.as_ref();
+        let passengers = resolve_passengers(&args, &provider.capabilities())?;
+        check_capabilities(&args, provider)?;
+        // Set default values if not provided
+        let trip_type = TripType::parse(args.trip_type.as_deref(), args.return_date.is_some())?;
+        let (departure_date, return_date) =
+            travel_dates(args.departure_date, args.return_date, trip_type, clock)?;
+        let today = clock.now().date_naive();
+        let departure = NaiveDate::parse_from_str(&departure_date, DATE_FORMAT)
+            .map_err(|e| FlightSearchError::InvalidResponse(e.to_string()))?;
+        let sort_by = args
+            .sort_by
+            .as_deref()
+            .map(SortBy::parse)
+            .transpose()?
+            .unwrap_or(SortBy::Price);
+        if let Some(max_price) = args.max_price
+            && (max_price.is_nan() || max_price <= 0.0)
+        {
+            return Err(FlightSearchError::InvalidArgument(format!(
+                "max_price must be a positive amount, got {}",
+                max_price
+            )));
+        }
+        let service = args.service.unwrap_or_else(|| "economy".to_string());
+        let Passengers {
+            adults,
+            children,
+            infants,
+        } = passengers;
+        let RegionSettings {
+            market,
+            currency,
+            locale,
+        } = resolve_region(
+            args.region.as_deref(),
+            args.market,
+            args.currency,
+            args.locale,
+        );
+        // Every outbound request of this search draws from one budget
+        let budget = RequestBudget::new(config.max_requests_per_search);
+        // Resolve source and destination to skyId/entityId in parallel
+        let (source_loc, dest_loc) = tokio::try_join!(
+            async {
+                resolve_location(provider, &args.source, &budget, &self.location_cache)
+                    .await
+                    .map_err(|e| e.context(&format!("Resolving source '{}'", args.source)))
+            },
+            async {
+                resolve_location(provider, &args.destination, &budget, &self.location_cache)
+                    .await
+                    .map_err(|e| {
+                        e.context(&format!("Resolving destination '{}'", args.destination))
+                    })
+            },
+        )?;
+        *route = Some((
+            source_loc.place_id().to_string(),
+            dest_loc.place_id().to_string(),
+        ));
+        check_airport_lists(&source_loc, &args.source, config)?;
+        check_airport_lists(&dest_loc, &args.destination, config)?;
+        // Searching a place against itself wastes the list request
+        if source_loc.sky_id == dest_loc.sky_id && source_loc.entity_id == dest_loc.entity_id {
+            return Err(FlightSearchError::InvalidArgument(
+                "source and destination are the same".to_string(),
+            ));
+        }
+        let params = SearchParams {
+            trip_type,
+            origin: source_loc.clone(),
+            destination: dest_loc.clone(),
+            departure_date: departure,
+            return_date: return_date
+                .as_deref()
+                .map(|date| NaiveDate::parse_from_str(date, DATE_FORMAT))
+                .transpose()
+                .map_err(|e| FlightSearchError::InvalidResponse(e.to_string()))?,
+            cabin: service.clone(),
+            adults,
+            children,
+            infants,
+            market: market.clone(),
+            currency: currency.clone(),
+            locale: locale.clone(),
+        };
+        let one_per_airline = args.one_per_airline.unwrap_or(false);
+        let group_by_stops = args.group_by_stops.unwrap_or(false);
+        let max_results = result_limit(args.max_results, config.default_max_results);
+        let filters = OptionFilters {
+            exclude_self_transfer: args.exclude_self_transfer.unwrap_or(false),
+            nonstop_only: args.nonstop_only.unwrap_or(false),
+            max_stops: args.max_stops,
+            refundable_only: args.refundable_only.unwrap_or(false),
+            keep_unknown_refundability: args.keep_unknown_refundability.unwrap_or(true),
+            max_quote_age: args.max_quote_age,
+            via: args.via.map(|via| via.trim().to_uppercase()),
+            allowed_airports: config.airport_allowlist.clone(),
+            denied_airports: config.airport_denylist.clone(),
+            strict_cabin: args
+                .strict_cabin
+                .unwrap_or(false)
+                .then(|| service.to_lowercase()),
+            max_price: args.max_price.map(|max| (max, currency.clone())),
+            min_agent_rating: args.min_agent_rating,
+        };
+        let mut currencies = vec![currency.clone()];
+        for extra in args.currencies.unwrap_or_default() {
+            let extra = extra.trim().to_uppercase();
+            if !extra.is_empty() && !currencies.contains(&extra) {
+                currencies.push(extra);
+            }
+        }
+        let search = SearchContext {
+            provider,
+            budget: &budget,
+            filters: &filters,
+            retry_on_empty: config.retry_on_empty,
+            max_degraded: config.max_degraded_results,
+        };
+        let mut result = search.search_currencies(&params, &currencies).await?;
+        if result.options.is_empty() && args.fallback_cabin.unwrap_or(false) {
+            for cabin in fallback_cabins(&config.cabin_order, &service) {
+                info!("No {} fares found, trying {}", service, cabin);
+                let cabin_params = SearchParams {
+                    cabin: cabin.clone(),
+                    ..params.clone()
+                };
+                result = search.search_currencies(&cabin_params, &currencies).await?;
+                if !result.options.is_empty() {
+                    result.fallback_cabin = Some(cabin);
+                    break;
+                }
+            }
+        }
+        if one_per_airline {
+            result.options = cheapest_per_airline(std::mem::take(&mut result.options));
+        }
+        if args.prefer_rated_agents.unwrap_or(false) {
+            prefer_rated_agents(&mut result.options);
+        }
+        sort_options(&mut result.options, sort_by);
+        group_by_currency(&mut result.options, &currency);
+        if group_by_stops {
+            result.options =
+                group_options_by_stops(std::mem::take(&mut result.options), max_results);
+        } else {
+            result.options.truncate(max_results);
+        }
+        if args.explain.unwrap_or(false) {
+            // Sections by stops are ordered by price within each section
+            let ranked_by = if group_by_stops {
+                SortBy::Stops
+            } else {
+                sort_by
+            };
+            let reasons = rank_reasons(&result.options, ranked_by, &currency);
+            for (option, reason) in result.options.iter_mut().zip(reasons) {
+                option.rank_reason = Some(reason);
+            }
+        }
+        // Re-runs the search with both dates moved by `offset` days
+        let search_shifted = |offset: i64| {
+            let params = params.shifted(offset);
+            let search = &search;
+            async move { search.search_once(&params).await }
+        };
+        if let Some(window) = args.price_calendar {
+            let selected = cheapest_price(&result);
+            match price_calendar(departure, today, window, selected, &search_shifted).await {
+                Ok(calendar) => result.price_calendar = Some(calendar),
+                Err(e) => warn!("Skipping price calendar: {}", e),
+            }
+        }
+        if args.compare_cabins.unwrap_or(false) {
+            let searched = result.fallback_cabin.clone().unwrap_or(service.clone());
+            let upgrades = premium_cabins(&config.cabin_order, &searched);
+            let searches = upgrades.iter().map(|cabin| {
+                let cabin_params = SearchParams {
+                    cabin: cabin.clone(),
+                    ..params.clone()
+                };
+                let search = &search;
+                async move {
+                    match search.search_once(&cabin_params).await {
+                        Ok(found) => CabinPrice {
+                            cabin: cabin.clone(),
+                            price: cheapest_price(&found),
+                            unavailable: false,
+                        },
+                        Err(e) => {
+                            warn!("Leaving {} unpriced in the cabin comparison: {}", cabin, e);
+                            CabinPrice {
+                                cabin: cabin.clone(),
+                                price: None,
+                                unavailable: true,
+                            }
+                        }
+                    }
+                }
+            });
+            let prices = join_all(searches).await;
+            result.cabin_comparison = Some(compare_cabin_prices(
+                CabinPrice {
+                    cabin: searched,
+                    price: cheapest_price(&result),
+                    unavailable: false,
+                },
+                prices,
+                args.premium_tolerance,
+            ));
+        }
+        result.origin_coordinates = source_loc.coordinates;
+        result.destination_coordinates = dest_loc.coordinates;
+        if args.show_distance.unwrap_or(false)
+            && let (Some(from), Some(to)) = (source_loc.coordinates, dest_loc.coordinates)
+        {
+            let distance = from.distance_km(&to);
+            result.distance_km = Some(distance);
+            for option in &mut result.options {
+                option.price_per_km = price_per_km(option, distance);
+            }
+        }
+        if args.booking_advice.unwrap_or(false) {
+            result.days_to_departure = Some((departure - today).num_days());
+        }
+        if args.book_by.unwrap_or(false) {
+            result.book_by = book_by_date(departure, today, config.book_by_lead_days);
+        }
+        if args.show_nearby_prices.unwrap_or(false) && !result.options.is_empty() {
+            match cheapest_on_nearby_days(&search_shifted).await {
+                Ok((day_before, day_after)) => {
+                    result.nearby_prices = Some(NearbyPrices {
+                        day_before,
+                        day_after,
+                    })
+                }
+                Err(e) => warn!("Skipping nearby date prices: {}", e),
+            }
+        }
+        if args.suggest_cheaper_dates.unwrap_or(false)
+            && let Some(selected) = cheapest_price(&result)
+        {
+            let window = PriceCalendarWindow {
+                days_before: CHEAPER_DATE_PROBE_DAYS,
+                days_after: CHEAPER_DATE_PROBE_DAYS,
+            };
+            match price_calendar(departure, today, window, Some(selected), &search_shifted).await {
+                Ok(calendar) => result.cheaper_date = cheaper_date(&calendar, departure, selected),
+                Err(e) => warn!("Skipping cheaper date suggestion: {}", e),
+            }
+        }
+        result.grouped_by_stops = group_by_stops;
+        result.query_echo = QueryEcho {
+            source: args.source,
+            destination: args.destination,
+            resolved_source: source_loc,
+            resolved_destination: dest_loc,
+            trip_type,
+            departure_date,
+            return_date,
+            cabin: service,
+            adults,
+            children,
+            infants,
+            market,
+            currency,
+            locale,
+            max_price: args.max_price,
+            searched_on: today,
+        };
+        if args.include_timing.unwrap_or(false) {
+            result.search_latency_ms = Some(started.elapsed().as_millis() as u64);
+        }
+        Ok(result)
+    }
+}
+
+impl Default for FlightSearchTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Tool for FlightSearchTool {
     const NAME: &'static str = "search_flights";
@@ -62,9 +887,52 @@ impl Tool for FlightSearchTool {
                     "destination": { "type": "string", "description": "Destination airport code or city name (e.g., 'DEL' or 'Delhi')" },
                     "departure_date": { "type": "string", "description": "Departure flight date in 'YYYY-MM-DD' format" },
                     "return_date": { "type": "string", "description": "Return flight date in 'YYYY-MM-DD' format" },
-                    "service": { "type": "string", "description": "Class of service", "enum": ["economy", "premium_economy", "business"] },
+                    "service": { "type": "string", "description": "Class of service", "enum": ["economy", "premium_economy", "business", "first"] },
                     "adults": { "type": "integer", "description": "Number of adults (over 12 years old)" },
-                    "currency": { "type": "string", "description": "Currency code (e.g., 'USD')" }
+                    "children": { "type": "integer", "description": "Number of children (2 to 12 years old)" },
+                    "infants": { "type": "integer", "description": "Number of lap infants (under 2 years old); each needs an accompanying adult" },
+                    "currency": { "type": "string", "description": "Currency code (e.g., 'USD')" },
+                    "exclude_self_transfer": { "type": "boolean", "description": "Exclude self-transfer itineraries built from separate tickets" },
+                    "currencies": { "type": "array", "items": { "type": "string" }, "description": "Additional currency codes to quote each fare in (e.g., ['EUR', 'GBP'])" },
+                    "booking_advice": { "type": "boolean", "description": "Append advice on whether now is a good time to book" },
+                    "response_format": { "type": "string", "description": "Format of the response; 'share' is a one-line summary for messaging, 'ndjson' one option per line", "enum": ["markdown", "json", "ndjson", "share"] },
+                    "show_nearby_prices": { "type": "boolean", "description": "Compare the cheapest fare with flying one day earlier or later" },
+                    "suggest_cheaper_dates": { "type": "boolean", "description": "Search up to two days either side and suggest a date that is notably cheaper; costs extra requests" },
+                    "region": { "type": "string", "description": "Region profile setting market, currency and locale together (e.g., 'es-ES')" },
+                    "market": { "type": "string", "description": "Market country code, overrides the region (e.g., 'US')" },
+                    "locale": { "type": "string", "description": "Locale for provider text, overrides the region (e.g., 'en-US')" },
+                    "sort_by": { "type": "string", "description": "Order of the results, cheapest first by default; 'duration' ranks by flight time, 'stops' by number of stops, 'value' by price per flight hour, 'departure' by departure time", "enum": ["price", "duration", "stops", "value", "departure"] },
+                    "price_calendar": {
+                        "type": "object",
+                        "description": "Return the cheapest fare for each day around the departure date (up to 7 days each side)",
+                        "properties": {
+                            "days_before": { "type": "integer" },
+                            "days_after": { "type": "integer" }
+                        }
+                    },
+                    "fallback_cabin": { "type": "boolean", "description": "Step down to lower cabins when the requested cabin has no fares" },
+                    "max_quote_age": { "type": "integer", "description": "Drop fares quoted more than this many seconds ago" },
+                    "one_per_airline": { "type": "boolean", "description": "Keep only the cheapest option from each airline" },
+                    "via": { "type": "string", "description": "Airport code the itinerary must connect through (e.g., 'DOH')" },
+                    "refundable_only": { "type": "boolean", "description": "Drop fares known to be non-refundable" },
+                    "keep_unknown_refundability": { "type": "boolean", "description": "With refundable_only, keep fares whose refundability isn't reported (default true)" },
+                    "nonstop_only": { "type": "boolean", "description": "Only return direct flights with no stops on the outbound leg" },
+                    "max_stops": { "type": "integer", "minimum": 0, "description": "Most stops allowed on the outbound leg; nonstop_only takes precedence when both are set" },
+                    "max_price": { "type": "number", "description": "Drop options priced above this amount, given in the same currency as `currency`" },
+                    "fare_breakdown": { "type": "boolean", "description": "Include base fare, taxes and carrier fees in JSON responses when available" },
+                    "compare_cabins": { "type": "boolean", "description": "Also quote the cheapest fare in each higher cabin" },
+                    "premium_tolerance": { "type": "number", "description": "With compare_cabins, only show higher cabins priced within this multiple of the searched cabin (e.g., 1.5)" },
+                    "include_timing": { "type": "boolean", "description": "Include search_latency_ms in JSON responses" },
+                    "show_weekdays": { "type": "boolean", "description": "Annotate travel dates with the day of the week (default true)" },
+                    "group_by_stops": { "type": "boolean", "description": "Group results into non-stop, 1 stop and 2+ stops sections, each sorted by price" },
+                    "min_agent_rating": { "type": "number", "description": "Drop fares sold by booking agents rated below this; fares without a rating are kept" },
+                    "prefer_rated_agents": { "type": "boolean", "description": "Among equally ranked fares, list higher-rated booking agents first" },
+                    "explain": { "type": "boolean", "description": "Note why each option ranks where it does under the chosen ordering" },
+                    "trip_type": { "type": "string", "enum": ["one_way", "round_trip"], "description": "one_way searches a single leg; defaults to round_trip when return_date is given, one_way otherwise" },
+                    "max_results": { "type": "integer", "minimum": 1, "maximum": 50, "description": format!("How many flight options to return, from 1 to 50. Set this whenever the user asks for a specific number, e.g. 3 for 'show me 3 options'. Defaults to {}", self.config.default_max_results) },
+                    "book_by": { "type": "boolean", "description": "Append a recommended date to book by, based on how far away departure is" },
+                    "strict_cabin": { "type": "boolean", "description": "Only return itineraries where every segment is in the requested cabin, dropping mixed-cabin ones" },
+                    "show_distance": { "type": "boolean", "description": "Show the great-circle distance of the route and each option's price per km" }
                 },
                 "required": ["source", "destination"]
             }),
@@ -73,355 +941,3957 @@ impl Tool for FlightSearchTool {
 
     #[instrument(name = "call_flight_search_tool")]
     async fn call(&self, args: FlightSearchArgs) -> Result<String, FlightSearchError> {
-        // Use the RapidAPI key from an environment variable
-        let api_key = env::var("RAPIDAPI_KEY").map_err(|_| FlightSearchError::MissingApiKey)?;
-        // Set default values if not provided
-        let departure_date = args.departure_date.unwrap_or_else(|| {
-            let date = Utc::now() + Duration::days(30);
-            date.format(DATE_FORMAT).to_string()
-        });
-        let service = args.service.unwrap_or_else(|| "economy".to_string());
-        let adults = args.adults.unwrap_or(1);
-        let children = 0; // Not in args yet
-        let infants = 0; // Not in args yet
-        let currency = args.currency.unwrap_or_else(|| "USD".to_string());
-        let market = "US".to_string();
-        // For roundtrip, use 7 days after departure date if only one date is provided
-        let in_date = departure_date.clone();
-        let return_date = args.return_date.unwrap_or_else(|| {
-            let dep_date = NaiveDate::parse_from_str(departure_date.as_str(), DATE_FORMAT)
-                .expect("Unable to parse departure_date");
-            let return_date = dep_date + Duration::days(7);
-            return_date.format(DATE_FORMAT).to_string()
-        });
-        let out_date = return_date.clone();
-        // Resolve source and destination to skyId/entityId
-        let source_loc = resolve_skyscanner_location(&api_key, &args.source).await?;
-        let dest_loc = resolve_skyscanner_location(&api_key, &args.destination).await?;
-        // Build Skyscanner query params
-        let mut query_params = HashMap::new();
-        query_params.insert("inDate", in_date.clone());
-        query_params.insert("outDate", out_date.clone());
-        query_params.insert("origin", source_loc.sky_id.clone());
-        query_params.insert("originId", source_loc.entity_id.clone());
-        query_params.insert("destination", dest_loc.sky_id.clone());
-        query_params.insert("destinationId", dest_loc.entity_id.clone());
-        query_params.insert("cabinClass", service.clone());
-        query_params.insert("adults", adults.to_string());
-        query_params.insert("children", children.to_string());
-        query_params.insert("infants", infants.to_string());
-        query_params.insert("market", market.clone());
-        query_params.insert("currency", currency.clone());
-        info!(
-            "Calling Skyscanner flights/roundtrip/list API with: {:?}",
-            query_params
-        );
-        let client = reqwest::Client::new();
-        let response = client
-            .get("https://skyscanner89.p.rapidapi.com/flights/roundtrip/list")
-            .headers({
-                let mut headers = reqwest::header::HeaderMap::new();
-                headers.insert(
-                    "X-RapidAPI-Host",
-                    "skyscanner89.p.rapidapi.com".parse().unwrap(),
-                );
-                headers.insert("X-RapidAPI-Key", api_key.parse().unwrap());
-                headers
-            })
-            .query(&query_params)
-            .send()
-            .await
-            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
-        let status = response.status();
-        let text = response
-            .text()
-            .await
-            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
-        if !status.is_success() {
-            error!(
-                "Skyscanner API call failed with status {}: response: {}",
-                status, text
-            );
-            let error =
-                FlightSearchError::ApiError(format!("Status: {}, Response: {}", status, text));
-            inc_flight_status_error(status.as_u16() as u64, &error);
-            return Err(error);
-        }
-        // Parse Skyscanner response and map to FlightOption(s)
-        let data: Value = serde_json::from_str(&text)
-            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
-        debug!("Parsed Skyscanner response: {:?}", data);
-        let mut flight_options = Vec::new();
-        // Support both wrapped and unwrapped responses
-        let itineraries = data
-            .get("itineraries")
-            .or_else(|| data.get("data").and_then(|d| d.get("itineraries")));
-        if let Some(itineraries) = itineraries {
-            if let Some(buckets) = itineraries.get("buckets").and_then(|b| b.as_array()) {
-                'outer: for bucket in buckets {
-                    if let Some(items) = bucket.get("items").and_then(|i| i.as_array()) {
-                        for item in items {
-                            // Extract airline name (first marketing carrier of first leg)
-                            let airline = item
-                                .get("legs")
-                                .and_then(|legs| legs.as_array())
-                                .and_then(|legs| legs.first())
-                                .and_then(|leg| leg.get("carriers"))
-                                .and_then(|carriers| carriers.get("marketing"))
-                                .and_then(|marketing| marketing.as_array())
-                                .and_then(|arr| arr.first())
-                                .and_then(|carrier| carrier.get("name"))
-                                .and_then(|n| n.as_str())
-                                .unwrap_or("Unknown Airline")
-                                .to_string();
-                            let flight_number = item
-                                .get("legs")
-                                .and_then(|legs| legs.as_array())
-                                .and_then(|legs| legs.first())
-                                .and_then(|leg| leg.get("segments"))
-                                .and_then(|segments| segments.as_array())
-                                .and_then(|segment| segment.first())
-                                .and_then(|leg| leg.get("flightNumber"))
-                                .and_then(|v| v.as_str())
-                                .unwrap_or("")
-                                .to_string();
-                            // Departure and arrival from first leg
-                            let departure = item
-                                .get("legs")
-                                .and_then(|legs| legs.as_array())
-                                .and_then(|legs| legs.first())
-                                .and_then(|leg| leg.get("departure"))
-                                .and_then(|d| d.as_str())
-                                .unwrap_or("")
-                                .to_string();
-                            let arrival = item
-                                .get("legs")
-                                .and_then(|legs| legs.as_array())
-                                .and_then(|legs| legs.first())
-                                .and_then(|leg| leg.get("arrival"))
-                                .and_then(|a| a.as_str())
-                                .unwrap_or("")
-                                .to_string();
-                            // Duration from first leg
-                            let duration = item
-                                .get("legs")
-                                .and_then(|legs| legs.as_array())
-                                .and_then(|legs| legs.first())
-                                .and_then(|leg| leg.get("durationInMinutes"))
-                                .and_then(|d| d.as_u64())
-                                .map(|mins| format!("{} hours {} minutes", mins / 60, mins % 60))
-                                .unwrap_or_else(|| "Unknown duration".to_string());
-                            // Stops from first leg
-                            let stops = item
-                                .get("legs")
-                                .and_then(|legs| legs.as_array())
-                                .and_then(|legs| legs.first())
-                                .and_then(|leg| leg.get("stopCount"))
-                                .and_then(|s| s.as_u64())
-                                .unwrap_or(0) as usize;
-                            // Price: use pricingOptions[0].price.amount or price.raw
-                            let price = item
-                                .get("pricingOptions")
-                                .and_then(|po| po.as_array())
-                                .and_then(|arr| arr.first())
-                                .and_then(|opt| opt.get("price"))
-                                .and_then(|p| p.get("amount"))
-                                .and_then(|a| a.as_f64())
-                                .or_else(|| {
-                                    item.get("price")
-                                        .and_then(|p| p.get("raw"))
-                                        .and_then(|a| a.as_f64())
-                                })
-                                .unwrap_or(0.0);
-                            // Currency: use pricingOptions[0].price.currencyCode or fallback to USD
-                            let currency = item
-                                .get("pricingOptions")
-                                .and_then(|po| po.as_array())
-                                .and_then(|arr| arr.first())
-                                .and_then(|opt| opt.get("price"))
-                                .and_then(|p| p.get("currencyCode"))
-                                .and_then(|c| c.as_str())
-                                .or_else(|| {
-                                    item.get("price")
-                                        .and_then(|p| p.get("currency"))
-                                        .and_then(|c| c.as_str())
-                                })
-                                .unwrap_or(&currency)
-                                .to_string();
-                            // Only push if price is nonzero
-                            if price > 0.0 {
-                                flight_options.push(FlightOption {
-                                    airline,
-                                    flight_number,
-                                    departure,
-                                    arrival,
-                                    duration,
-                                    stops,
-                                    price,
-                                    currency,
-                                });
-                            }
-                            if flight_options.len() >= 5 {
-                                break 'outer;
-                            }
-                        }
+        // Decrements when dropped, however the call returns
+        let _in_flight = track_in_flight();
+        let started = Instant::now();
+        let response_format = args.response_format.clone();
+        let fare_breakdown = args.fare_breakdown.unwrap_or(false);
+        let show_weekdays = args.show_weekdays.unwrap_or(true);
+        let result = self.search_structured(args).await;
+        record_flight_search_duration(elapsed_ms(started), result.is_ok());
+        let mut result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                error!(code = e.code(), "Flight search failed: {}", e);
+                return match response_format.as_deref() {
+                    // Structured callers branch on the code rather than the message
+                    Some("json" | "ndjson") => Ok(error_json(&e)),
+                    _ => Err(e),
+                };
+            }
+        };
+        match response_format.as_deref() {
+            Some(format @ ("json" | "ndjson")) => {
+                if !fare_breakdown {
+                    for option in &mut result.options {
+                        option.fare_breakdown = None;
                     }
                 }
-            }
-        }
-        if flight_options.is_empty() {
-            return Ok("No flights found for the given criteria.".to_string());
-        }
-        // Generate response for LLM
-        let mut output = String::new();
-        output.push_str("Here are some flight options:\n\n");
-        for (i, option) in flight_options.iter().enumerate() {
-            output.push_str(&format!("{}. **Airline**: {}\n", i + 1, option.airline));
-            output.push_str(&format!(
-                "   - **Flight Number**: {}\n",
-                option.flight_number
-            ));
-            output.push_str(&format!("   - **Departure**: {}\n", option.departure));
-            output.push_str(&format!("   - **Arrival**: {}\n", option.arrival));
-            output.push_str(&format!("   - **Duration**: {}\n", option.duration));
-            output.push_str(&format!(
-                "   - **Stops**: {}\n",
-                if option.stops == 0 {
-                    "Non-stop".to_string()
+                if format == "ndjson" {
+                    to_ndjson(&result.options)
                 } else {
-                    format!("{} stop(s)", option.stops)
+                    serde_json::to_string(&result)
                 }
-            ));
-            output.push_str(&format!(
-                "   - **Price**: {:.2} {}\n",
-                option.price, option.currency
-            ));
+                .map_err(|e| FlightSearchError::InvalidResponse(e.to_string()))
+            }
+            Some("share") if !result.options.is_empty() => Ok(share_text(
+                &result.options,
+                labels_for_locale(&result.query_echo.locale),
+            )),
+            _ => Ok(result.to_markdown(show_weekdays)),
         }
-        inc_flight_status_success();
-        Ok(output)
     }
 }
 
-#[instrument(name = "resolve_skyscanner_location")]
-async fn resolve_skyscanner_location(
-    api_key: &str,
-    query: &str,
-) -> Result<SkyscannerLocation, FlightSearchError> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://skyscanner89.p.rapidapi.com/flights/auto-complete")
-        .headers({
-            let mut headers = reqwest::header::HeaderMap::new();
-            headers.insert(
-                "X-RapidAPI-Host",
-                "skyscanner89.p.rapidapi.com".parse().unwrap(),
-            );
-            headers.insert("X-RapidAPI-Key", api_key.parse().unwrap());
-            headers
-        })
-        .query(&[("query", query)])
-        .send()
-        .await
-        .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
-    let status = response.status();
-    let text = response
-        .text()
-        .await
-        .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
-    if !status.is_success() {
-        return Err(FlightSearchError::ApiError(format!(
-            "Auto-complete failed: {}: {}",
-            status, text
+/// Rejects arguments that need a feature the provider can't serve instead of ignoring them
+fn check_capabilities(
+    args: &FlightSearchArgs,
+    provider: &dyn FlightProvider,
+) -> Result<(), FlightSearchError> {
+    let capabilities = provider.capabilities();
+    let requested = [
+        (
+            "price_calendar",
+            args.price_calendar.is_some(),
+            capabilities.price_calendar,
+        ),
+        (
+            "compare_cabins",
+            args.compare_cabins.unwrap_or(false),
+            capabilities.cabin_comparison,
+        ),
+        (
+            "show_nearby_prices",
+            args.show_nearby_prices.unwrap_or(false),
+            capabilities.nearby_prices,
+        ),
+        (
+            "suggest_cheaper_dates",
+            args.suggest_cheaper_dates.unwrap_or(false),
+            capabilities.nearby_prices,
+        ),
+        (
+            "currencies",
+            args.currencies.as_ref().is_some_and(|c| !c.is_empty()),
+            capabilities.multi_currency,
+        ),
+        (
+            "exclude_self_transfer",
+            args.exclude_self_transfer.unwrap_or(false),
+            capabilities.self_transfer_filter,
+        ),
+        (
+            "max_quote_age",
+            args.max_quote_age.is_some(),
+            capabilities.quote_age,
+        ),
+    ];
+    if let Some((feature, _, _)) = requested
+        .into_iter()
+        .find(|(_, wanted, supported)| *wanted && !*supported)
+    {
+        return Err(FlightSearchError::UnsupportedFeature {
+            provider: provider.name(),
+            feature,
+        });
+    }
+    Ok(())
+}
+
+/// Travellers on one booking
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Passengers {
+    pub adults: u8,
+    pub children: u8,
+    pub infants: u8,
+}
+
+impl Passengers {
+    pub fn total(&self) -> u32 {
+        self.adults as u32 + self.children as u32 + self.infants as u32
+    }
+}
+
+/// Fills in the passenger defaults (one adult, no children or infants) and checks
+/// the party against the rules every provider shares and the provider's size limit
+fn resolve_passengers(
+    args: &FlightSearchArgs,
+    capabilities: &ProviderCapabilities,
+) -> Result<Passengers, FlightSearchError> {
+    let passengers = Passengers {
+        adults: args.adults.unwrap_or(1),
+        children: args.children.unwrap_or(0),
+        infants: args.infants.unwrap_or(0),
+    };
+    if passengers.adults == 0 {
+        return Err(FlightSearchError::InvalidArgument(
+            "at least one adult must travel".to_string(),
+        ));
+    }
+    // Lap infants travel on an adult's lap, so there can't be more of them than adults
+    if passengers.infants > passengers.adults {
+        return Err(FlightSearchError::InvalidArgument(format!(
+            "{} infants need at least as many adults, got {}",
+            passengers.infants, passengers.adults
         )));
     }
-    let data: Value = serde_json::from_str(&text)
-        .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
-    // Use inputSuggest array per schema
-    if let Some(suggestions) = data.get("inputSuggest").and_then(|d| d.as_array()) {
-        for item in suggestions {
-            if let Some(nav) = item.get("navigation") {
-                if let Some(params) = nav.get("relevantFlightParams") {
-                    if let (Some(sky_id), Some(entity_id)) = (
-                        params.get("skyId").and_then(|v| v.as_str()),
-                        params.get("entityId").and_then(|v| v.as_str()),
-                    ) {
-                        return Ok(SkyscannerLocation {
-                            sky_id: sky_id.to_string(),
-                            entity_id: entity_id.to_string(),
-                        });
-                    }
-                }
+    // Larger groups are rejected rather than split into sub-parties, since separate
+    // searches can't guarantee the whole group gets seats on the same flights
+    if let Some(max) = capabilities.max_passengers
+        && passengers.total() > max
+    {
+        return Err(FlightSearchError::PartyTooLarge {
+            requested: passengers.total(),
+            max,
+        });
+    }
+    Ok(passengers)
+}
+
+/// Notes which endpoints were searched as whole metro areas, e.g. "London (all airports)"
+fn metro_note(locations: &[&Location], labels: &OutputLabels) -> Option<String> {
+    let metros: Vec<String> = locations
+        .iter()
+        .filter_map(|location| location.metro_name.as_deref())
+        .map(|name| format!("{} ({})", name, labels.all_airports))
+        .collect();
+    if metros.is_empty() {
+        return None;
+    }
+    Some(format!("{}: {}", labels.metro_search, metros.join(", ")))
+}
+
+/// Fills in missing travel dates so the pair is always coherent. One-way trips only
+/// get a departure. For roundtrips a missing return is a week after departure. A missing departure defaults to 30 days out, but is
+/// pulled back to a week before an earlier return, and never before today. A given
+/// departure before today (UTC) is rejected.
+fn travel_dates(
+    departure_date: Option<String>,
+    return_date: Option<String>,
+    trip_type: TripType,
+    clock: &dyn Clock,
+) -> Result<(String, Option<String>), FlightSearchError> {
+    let today = clock.now().date_naive();
+    let parse = |name: &str, value: &str| {
+        NaiveDate::parse_from_str(value, DATE_FORMAT).map_err(|_| {
+            FlightSearchError::InvalidArgument(format!(
+                "{} must be in YYYY-MM-DD format, got '{}'",
+                name, value
+            ))
+        })
+    };
+    let parse_departure = |value: &str| {
+        let departure = parse("departure_date", value)?;
+        if departure < today {
+            return Err(FlightSearchError::InvalidArgument(format!(
+                "departure_date {} is in the past; today is {}",
+                value,
+                today.format(DATE_FORMAT)
+            )));
+        }
+        Ok(departure)
+    };
+    if trip_type == TripType::OneWay {
+        if return_date.is_some() {
+            return Err(FlightSearchError::InvalidArgument(
+                "return_date can't be used with a one_way trip_type".to_string(),
+            ));
+        }
+        let departure = departure_date.unwrap_or_else(|| default_departure_date(clock));
+        let departure = parse_departure(&departure)?;
+        return Ok((departure.format(DATE_FORMAT).to_string(), None));
+    }
+    let return_date = return_date
+        .map(|date| parse("return_date", &date))
+        .transpose()?;
+    let departure = match (departure_date, return_date) {
+        (Some(date), _) => parse_departure(&date)?,
+        (None, None) => parse("departure_date", &default_departure_date(clock))?,
+        (None, Some(return_date)) => {
+            if return_date < today {
+                return Err(FlightSearchError::InvalidArgument(format!(
+                    "return_date {} is in the past; provide a departure_date",
+                    return_date.format(DATE_FORMAT)
+                )));
             }
+            let default = parse("departure_date", &default_departure_date(clock))?;
+            default.min(return_date - Duration::days(7)).max(today)
         }
+    };
+    let return_date = return_date.unwrap_or(departure + Duration::days(7));
+    if return_date < departure {
+        return Err(FlightSearchError::InvalidArgument(format!(
+            "return_date {} is before departure_date {}",
+            return_date.format(DATE_FORMAT),
+            departure.format(DATE_FORMAT)
+        )));
     }
-    Err(FlightSearchError::InvalidResponse(
-        "No valid airport found in auto-complete response".to_string(),
+    Ok((
+        departure.format(DATE_FORMAT).to_string(),
+        Some(return_date.format(DATE_FORMAT).to_string()),
     ))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::env;
-
-    fn cleanup_test_env() {
-        unsafe { env::remove_var("RAPIDAPI_KEY") };
+/// Rejects a resolved location outside the configured airport allowlist or on the
+/// denylist, before any flights are searched
+fn check_airport_lists(
+    location: &Location,
+    query: &str,
+    config: &Config,
+) -> Result<(), FlightSearchError> {
+    // Entity-only locations have no sky id, so they are checked by entity id
+    let code = location.place_id().to_uppercase();
+    if config.airport_denylist.contains(&code) {
+        return Err(FlightSearchError::InvalidArgument(format!(
+            "'{}' resolves to {}, which is on the airport denylist",
+            query, code
+        )));
+    }
+    if !config.airport_allowlist.is_empty() && !config.airport_allowlist.contains(&code) {
+        return Err(FlightSearchError::InvalidArgument(format!(
+            "'{}' resolves to {}, which is not on the airport allowlist",
+            query, code
+        )));
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_flight_search_args_validation() {
-        let tool = FlightSearchTool;
+/// Departure used when the model omits one: 30 days from today
+fn default_departure_date(clock: &dyn Clock) -> String {
+    let date = clock.now() + Duration::days(30);
+    date.format(DATE_FORMAT).to_string()
+}
 
-        // Test with empty source
-        let args = FlightSearchArgs {
-            source: "".to_string(),
-            destination: "DEL".to_string(),
-            ..Default::default()
-        };
-        let result = tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(tool.call(args));
-        assert!(result.is_err());
+/// Market, currency and locale sent with a search
+#[derive(Debug, PartialEq)]
+struct RegionSettings {
+    market: String,
+    currency: String,
+    locale: String,
+}
 
-        // Test with empty destination
-        let args = FlightSearchArgs {
-            source: "BOM".to_string(),
-            destination: "".to_string(),
-            ..Default::default()
-        };
-        let result = tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(tool.call(args));
-        assert!(result.is_err());
+/// Expands a region profile into consistent defaults; explicit values win
+fn resolve_region(
+    region: Option<&str>,
+    market: Option<String>,
+    currency: Option<String>,
+    locale: Option<String>,
+) -> RegionSettings {
+    let region = region.unwrap_or("en-US").trim();
+    let (default_market, default_currency, default_locale) = REGION_PROFILES
+        .iter()
+        .find(|(name, ..)| name.eq_ignore_ascii_case(region))
+        .map(|(_, market, currency, locale)| {
+            (market.to_string(), currency.to_string(), locale.to_string())
+        })
+        .unwrap_or_else(|| {
+            // Unknown profiles still carry a country, e.g. "nl-NL"
+            let country = region.rsplit('-').next().unwrap_or("US").to_uppercase();
+            (country, "USD".to_string(), region.to_string())
+        });
+    RegionSettings {
+        market: market.unwrap_or(default_market),
+        currency: currency.unwrap_or(default_currency),
+        locale: locale.unwrap_or(default_locale),
     }
+}
 
-    #[test]
-    fn test_flight_search_tool_definition() {
-        let tool = FlightSearchTool;
-        let definition = tokio::runtime::Runtime::new()
-            .unwrap()
-            .block_on(tool.definition("test".to_string()));
+/// Shared state for the list requests of one logical search
+struct SearchContext<'a> {
+    provider: &'a dyn FlightProvider,
+    budget: &'a RequestBudget,
+    filters: &'a OptionFilters,
+    retry_on_empty: bool,
+    max_degraded: Option<f64>,
+}
 
-        assert_eq!(definition.name, "search_flights");
-        assert!(definition.description.contains("Search for flights"));
-        assert!(definition.parameters.to_string().contains("source"));
-        assert!(definition.parameters.to_string().contains("destination"));
+impl SearchContext<'_> {
+    /// Fetches and parses a single list response
+    async fn search_once(
+        &self,
+        params: &SearchParams,
+    ) -> Result<FlightSearchResult, FlightSearchError> {
+        let results = self.provider.search(params, self.budget).await?;
+        check_result_quality(&results.options, self.max_degraded)?;
+        // Results are always sorted, so every option is kept until they are
+        // truncated to the best ones
+        Ok(parse_search_result(results, self.filters, usize::MAX))
     }
 
-    #[test]
-    fn test_missing_api_key_error() {
-        cleanup_test_env(); // Ensure no API key is set
-        let tool = FlightSearchTool;
-        let args = FlightSearchArgs {
-            source: "BOM".to_string(),
-            destination: "DEL".to_string(),
-            ..Default::default()
-        };
-
-        let result = tokio::runtime::Runtime::new()
+    /// Runs one search per currency concurrently, since each extra currency is a
+    /// separate search so every quote comes from the provider, then merges them
+    async fn search_currencies(
+        &self,
+        params: &SearchParams,
+        currencies: &[String],
+    ) -> Result<FlightSearchResult, FlightSearchError> {
+        let searches = currencies.iter().map(|currency| {
+            let params = SearchParams {
+                currency: currency.clone(),
+                ..params.clone()
+            };
+            async move {
+                retry_on_empty(self.retry_on_empty, EMPTY_RETRY_DELAY, || {
+                    self.search_once(&params)
+                })
+                .await
+            }
+        });
+        Ok(merge_currency_results(try_join_all(searches).await?))
+    }
+}
+
+/// Repeats a search once after `delay` when it succeeds with zero options,
+/// since valid routes occasionally return an empty bucket transiently.
+/// Errors are returned as-is; this is not an error retry.
+async fn retry_on_empty<F, Fut>(
+    enabled: bool,
+    delay: StdDuration,
+    mut search: F,
+) -> Result<FlightSearchResult, FlightSearchError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<FlightSearchResult, FlightSearchError>>,
+{
+    let result = search().await?;
+    if !result.options.is_empty() || !enabled {
+        return Ok(result);
+    }
+    info!(
+        "Search returned no options, retrying once after {:?}",
+        delay
+    );
+    tokio::time::sleep(delay).await;
+    search().await
+}
+
+/// Merges per-currency searches into the first search's options, matching
+/// itineraries by the flight numbers and times of every leg
+fn merge_currency_results(results: Vec<FlightSearchResult>) -> FlightSearchResult {
+    let mut results = results.into_iter();
+    let mut merged = results.next().unwrap_or_default();
+    for result in results {
+        merged.results_complete &= result.results_complete;
+        for option in result.options {
+            let key = option.itinerary_key();
+            if let Some(existing) = merged.options.iter_mut().find(|o| o.itinerary_key() == key) {
+                existing.prices.extend(option.prices);
+            }
+        }
+    }
+    // Any unfinished currency search leaves the merged prices unsettled
+    for option in &mut merged.options {
+        option.price_confidence = PriceConfidence::from_complete(merged.results_complete);
+    }
+    merged
+}
+
+/// Returns the cheapest fares one day before and one day after the searched dates
+async fn cheapest_on_nearby_days<F, Fut>(
+    mut search: F,
+) -> Result<(Option<f64>, Option<f64>), FlightSearchError>
+where
+    F: FnMut(i64) -> Fut,
+    Fut: Future<Output = Result<FlightSearchResult, FlightSearchError>>,
+{
+    let (before, after) = tokio::try_join!(search(-1), search(1))?;
+    Ok((cheapest_price(&before), cheapest_price(&after)))
+}
+
+/// Collects the cheapest fare for every date in the window using bounded concurrency.
+/// The selected date reuses the main search's cheapest fare. Earlier dates are only
+/// probed as far back as `today`.
+async fn price_calendar<F, Fut>(
+    departure: NaiveDate,
+    today: NaiveDate,
+    window: PriceCalendarWindow,
+    selected: Option<f64>,
+    search: F,
+) -> Result<BTreeMap<String, Option<f64>>, FlightSearchError>
+where
+    F: Fn(i64) -> Fut,
+    Fut: Future<Output = Result<FlightSearchResult, FlightSearchError>>,
+{
+    let days_left = (departure - today).num_days().max(0);
+    let before = (window.days_before.min(MAX_CALENDAR_DAYS) as i64).min(days_left);
+    let after = window.days_after.min(MAX_CALENDAR_DAYS) as i64;
+    let search = &search;
+    let prices: Vec<(i64, Option<f64>)> = stream::iter((-before..=after).filter(|o| *o != 0))
+        .map(|offset| async move {
+            search(offset)
+                .await
+                .map(|result| (offset, cheapest_price(&result)))
+        })
+        .buffer_unordered(CALENDAR_CONCURRENCY)
+        .try_collect()
+        .await?;
+    let mut calendar: BTreeMap<String, Option<f64>> = prices
+        .into_iter()
+        .map(|(offset, price)| {
+            let date = departure + Duration::days(offset);
+            (date.format(DATE_FORMAT).to_string(), price)
+        })
+        .collect();
+    calendar.insert(departure.format(DATE_FORMAT).to_string(), selected);
+    Ok(calendar)
+}
+
+/// Renders a price calendar as one line per date, marking the selected date
+fn format_price_calendar(
+    calendar: &BTreeMap<String, Option<f64>>,
+    selected: &str,
+    currency: &str,
+    labels: &OutputLabels,
+) -> String {
+    let mut output = format!("{}:\n", labels.price_calendar);
+    for (date, price) in calendar {
+        let marker = if date == selected { "*" } else { " " };
+        let price = match price {
+            Some(price) => format!("{:.2} {}", price, currency),
+            None => labels.no_fares.to_string(),
+        };
+        output.push_str(&format!("{} {}: {}\n", marker, date, price));
+    }
+    output
+}
+
+fn cheapest_price(result: &FlightSearchResult) -> Option<f64> {
+    result
+        .options
+        .iter()
+        .map(|option| option.price)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+fn nearby_price_line(
+    currency: &str,
+    before: Option<f64>,
+    selected: Option<f64>,
+    after: Option<f64>,
+    labels: &OutputLabels,
+) -> String {
+    let price = |price: Option<f64>| match price {
+        Some(price) => format!("{:.2} {}", price, currency),
+        None => labels.not_available.to_string(),
+    };
+    format!(
+        "{}: {}, {}: {}, {}: {}",
+        labels.day_before,
+        price(before),
+        labels.selected,
+        price(selected),
+        labels.day_after,
+        price(after)
+    )
+}
+
+/// The cheapest other date in `calendar` when it saves at least
+/// `CHEAPER_DATE_MIN_SAVING` of the selected fare
+fn cheaper_date(
+    calendar: &BTreeMap<String, Option<f64>>,
+    departure: NaiveDate,
+    selected: f64,
+) -> Option<CheaperDate> {
+    let (date, price) = calendar
+        .iter()
+        .filter_map(|(date, price)| {
+            let date = NaiveDate::parse_from_str(date, DATE_FORMAT).ok()?;
+            Some((date, (*price)?))
+        })
+        .filter(|(date, _)| *date != departure)
+        .min_by(|a, b| a.1.total_cmp(&b.1))?;
+    let saving = selected - price;
+    if saving < selected * CHEAPER_DATE_MIN_SAVING {
+        return None;
+    }
+    Some(CheaperDate {
+        date,
+        days_offset: (date - departure).num_days(),
+        price,
+        saving,
+    })
+}
+
+/// e.g. "Flying 2 days later is 120.00 USD cheaper (420.00 USD on 2025-06-03)"
+fn cheaper_date_line(cheaper: &CheaperDate, currency: &str, labels: &OutputLabels) -> String {
+    let offset = cheaper.days_offset;
+    let days = if offset.abs() == 1 {
+        labels.day
+    } else {
+        labels.days
+    };
+    let direction = if offset > 0 {
+        labels.later
+    } else {
+        labels.earlier
+    };
+    format!(
+        "{} {} {} {} {} {:.2} {} {} ({:.2} {} {} {})",
+        labels.flying,
+        offset.abs(),
+        days,
+        direction,
+        labels.saving_is,
+        cheaper.saving,
+        currency,
+        labels.cheaper,
+        cheaper.price,
+        currency,
+        labels.on_date,
+        cheaper.date.format(DATE_FORMAT)
+    )
+}
+
+/// Describes how good the booking window is for a departure `days_out` days away
+fn lead_time_advice(days_out: i64, labels: &OutputLabels) -> String {
+    let advice = LEAD_TIME_HORIZONS
+        .iter()
+        .position(|min_days| days_out >= *min_days)
+        .map(|i| labels.lead_time_advice[i])
+        .unwrap_or(labels.departure_passed);
+    format!(
+        "{} {} {} — {}",
+        labels.booking, days_out, labels.days_out, advice
+    )
+}
+
+/// Recommended booking deadline: `lead_days` before departure, or today when that has
+/// already passed. `None` once the departure date itself is in the past.
+fn book_by_date(departure: NaiveDate, today: NaiveDate, lead_days: u32) -> Option<NaiveDate> {
+    if departure < today {
+        return None;
+    }
+    Some((departure - Duration::days(lead_days as i64)).max(today))
+}
+
+fn book_by_line(date: NaiveDate, today: NaiveDate, labels: &OutputLabels) -> String {
+    if date == today {
+        labels.book_now.to_string()
+    } else {
+        format!("{} {}", labels.book_by, date.format(DATE_FORMAT))
+    }
+}
+
+/// Cabins below `requested` in the configured hierarchy, nearest first
+fn fallback_cabins(cabin_order: &[String], requested: &str) -> Vec<String> {
+    match cabin_order
+        .iter()
+        .position(|cabin| cabin.eq_ignore_ascii_case(requested))
+    {
+        Some(pos) => cabin_order[..pos].iter().rev().cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Cabins above `requested` in the configured hierarchy, nearest first
+fn premium_cabins(cabin_order: &[String], requested: &str) -> Vec<String> {
+    match cabin_order
+        .iter()
+        .position(|cabin| cabin.eq_ignore_ascii_case(requested))
+    {
+        Some(pos) => cabin_order[pos + 1..].to_vec(),
+        None => Vec::new(),
+    }
+}
+
+/// Lists the searched cabin first, then each higher cabin with fares or a failed
+/// search. With a `tolerance`, higher cabins costing more than that multiple of
+/// the searched cabin's fare are left out.
+fn compare_cabin_prices(
+    searched: CabinPrice,
+    upgrades: Vec<CabinPrice>,
+    tolerance: Option<f64>,
+) -> Vec<CabinPrice> {
+    let limit = match (searched.price, tolerance) {
+        (Some(base), Some(tolerance)) => base * tolerance,
+        _ => f64::INFINITY,
+    };
+    let mut comparison = vec![searched];
+    comparison.extend(upgrades.into_iter().filter(|upgrade| {
+        upgrade.unavailable || upgrade.price.is_some_and(|price| price <= limit)
+    }));
+    comparison
+}
+
+fn format_cabin_comparison(
+    comparison: &[CabinPrice],
+    currency: &str,
+    labels: &OutputLabels,
+) -> String {
+    let mut output = format!("{}:\n", labels.cabin_comparison);
+    let base = comparison.first().and_then(|c| c.price);
+    for (i, entry) in comparison.iter().enumerate() {
+        let price = match (entry.price, base) {
+            _ if entry.unavailable => labels.price_unavailable.to_string(),
+            (Some(price), Some(base)) if i > 0 => {
+                format!("{:.2} {} ({:.1}x)", price, currency, price / base)
+            }
+            (Some(price), _) => format!("{:.2} {}", price, currency),
+            (None, _) => labels.no_fares.to_string(),
+        };
+        output.push_str(&format!("- {}: {}\n", entry.cabin, price));
+    }
+    output
+}
+
+/// Supported result orderings
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortBy {
+    /// Cheapest first
+    Price,
+    /// Shortest flight time first
+    Duration,
+    /// Fewest stops first, cheapest among equal stops
+    Stops,
+    /// Cheapest price per flight hour first
+    Value,
+    /// Earliest departure first
+    Departure,
+}
+
+impl SortBy {
+    fn parse(value: &str) -> Result<Self, FlightSearchError> {
+        match value.trim().to_lowercase().as_str() {
+            "price" => Ok(SortBy::Price),
+            "duration" => Ok(SortBy::Duration),
+            "stops" => Ok(SortBy::Stops),
+            "value" => Ok(SortBy::Value),
+            "departure" => Ok(SortBy::Departure),
+            other => Err(FlightSearchError::InvalidArgument(format!(
+                "Unsupported sort_by value: {}",
+                other
+            ))),
+        }
+    }
+}
+
+fn sort_options(options: &mut [FlightOption], sort_by: SortBy) {
+    match sort_by {
+        SortBy::Price => options.sort_by(|a, b| a.price.total_cmp(&b.price)),
+        // Options without a known duration rank last
+        SortBy::Duration => options
+            .sort_by(|a, b| known_first(a.duration_minutes, b.duration_minutes, |a, b| a.cmp(&b))),
+        SortBy::Stops => options.sort_by(|a, b| {
+            a.stops
+                .cmp(&b.stops)
+                .then_with(|| a.price.total_cmp(&b.price))
+        }),
+        // Options without a known duration rank last
+        SortBy::Value => options.sort_by(|a, b| {
+            known_first(a.price_per_hour, b.price_per_hour, |a, b| a.total_cmp(&b))
+        }),
+        // Options with unparseable departure times rank last
+        SortBy::Departure => options.sort_by_cached_key(|option| {
+            let departure = parse_datetime(&option.departure);
+            (departure.is_none(), departure)
+        }),
+    }
+}
+
+/// Explains each option's place under `sort_by`, measured against the best ranked
+/// option so the notes stay true after grouping or truncation
+fn rank_reasons(options: &[FlightOption], sort_by: SortBy, requested: &str) -> Vec<String> {
+    let comparable: Vec<&FlightOption> = options.iter().filter(|o| !o.currency_mismatch).collect();
+    let Some(best) = comparable.first() else {
+        return options
+            .iter()
+            .map(|o| mismatch_reason(o, requested))
+            .collect();
+    };
+    let stop_text = |stops: usize| match stops {
+        0 => "nonstop".to_string(),
+        1 => "1 stop".to_string(),
+        n => format!("{} stops", n),
+    };
+    options
+        .iter()
+        .enumerate()
+        .map(|(i, option)| {
+            if option.currency_mismatch {
+                return mismatch_reason(option, requested);
+            }
+            let first = std::ptr::eq(option, *best);
+            match sort_by {
+                SortBy::Price if first => "lowest price".to_string(),
+                SortBy::Price => format!(
+                    "{:.2} {} more than the cheapest",
+                    option.price - best.price,
+                    option.currency
+                ),
+                SortBy::Duration => match (option.duration_minutes, best.duration_minutes) {
+                    _ if first => "shortest flight time".to_string(),
+                    (Some(minutes), Some(fastest)) => format!(
+                        "{} longer than the fastest",
+                        format_duration(Some(minutes.saturating_sub(fastest)))
+                    ),
+                    _ => "flight time unknown, ranked last".to_string(),
+                },
+                SortBy::Stops if first => "fewest stops".to_string(),
+                SortBy::Stops => {
+                    let cheapest = options[..i]
+                        .iter()
+                        .find(|o| o.stops == option.stops && !o.currency_mismatch);
+                    match cheapest {
+                        Some(cheapest) => format!(
+                            "{}, {:.2} {} more than the cheapest with as many stops",
+                            stop_text(option.stops),
+                            option.price - cheapest.price,
+                            option.currency
+                        ),
+                        None => format!("cheapest {}", stop_text(option.stops)),
+                    }
+                }
+                SortBy::Value if first => "lowest price per flight hour".to_string(),
+                SortBy::Value => match option.price_per_hour {
+                    Some(per_hour) => {
+                        format!("{:.2} {} per flight hour", per_hour, option.currency)
+                    }
+                    None => "flight time unknown, ranked last".to_string(),
+                },
+                SortBy::Departure => match (
+                    parse_datetime(&option.departure),
+                    parse_datetime(&best.departure),
+                ) {
+                    _ if first => "earliest departure".to_string(),
+                    (Some(departure), Some(earliest)) => {
+                        let minutes = (departure - earliest).num_minutes().max(0) as u64;
+                        format!(
+                            "departs {} after the earliest",
+                            format_duration(Some(minutes))
+                        )
+                    }
+                    _ => "departure time unknown, ranked last".to_string(),
+                },
+            }
+        })
+        .collect()
+}
+
+fn mismatch_reason(option: &FlightOption, requested: &str) -> String {
+    format!(
+        "quoted in {}, listed after fares in {}",
+        option.currency, requested
+    )
+}
+
+/// Orders options by agent rating, best first and unrated last. The sorts are
+/// stable, so running this before `sort_options` breaks ties in the agents' favour.
+fn prefer_rated_agents(options: &mut [FlightOption]) {
+    options.sort_by(|a, b| known_first(a.agent_rating, b.agent_rating, |a, b| b.total_cmp(&a)));
+}
+
+/// Moves options quoted in a currency other than `requested` after the rest and
+/// flags them, so prices are never compared across currencies. Order within each
+/// group is preserved.
+fn group_by_currency(options: &mut [FlightOption], requested: &str) {
+    for option in options.iter_mut() {
+        option.currency_mismatch = !option.currency.eq_ignore_ascii_case(requested);
+    }
+    options.sort_by_key(|option| option.currency_mismatch);
+}
+
+/// Number of options to return: the requested count clamped to `MAX_RESULTS_RANGE`,
+/// or the configured default
+fn result_limit(requested: Option<i64>, default: usize) -> usize {
+    let (min, max) = (*MAX_RESULTS_RANGE.start(), *MAX_RESULTS_RANGE.end());
+    match requested {
+        None => default.clamp(min, max),
+        Some(requested) => {
+            let limit = requested.clamp(min as i64, max as i64) as usize;
+            if limit as i64 != requested {
+                warn!(
+                    "max_results {} is outside {}..={}, using {}",
+                    requested, min, max, limit
+                );
+            }
+            limit
+        }
+    }
+}
+
+/// Section index for a stop count: non-stop, one stop, or two and more
+fn stops_section(stops: usize) -> usize {
+    stops.min(2)
+}
+
+/// Orders options by stops section, then price, keeping up to `per_section` in each
+fn group_options_by_stops(mut options: Vec<FlightOption>, per_section: usize) -> Vec<FlightOption> {
+    options.sort_by(|a, b| {
+        stops_section(a.stops)
+            .cmp(&stops_section(b.stops))
+            .then(a.price.total_cmp(&b.price))
+    });
+    let mut counts = [0usize; 3];
+    options.retain(|option| {
+        let count = &mut counts[stops_section(option.stops)];
+        *count += 1;
+        *count <= per_section
+    });
+    options
+}
+
+/// Keeps the cheapest option of each airline, ordered by price
+fn cheapest_per_airline(mut options: Vec<FlightOption>) -> Vec<FlightOption> {
+    options.sort_by(|a, b| a.price.total_cmp(&b.price));
+    let mut seen = std::collections::HashSet::new();
+    options.retain(|option| seen.insert(option.airline.clone()));
+    options
+}
+
+/// Orders known values with `cmp` and puts unknown values after them
+fn known_first<T>(
+    a: Option<T>,
+    b: Option<T>,
+    cmp: impl Fn(T, T) -> std::cmp::Ordering,
+) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp(a, b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+/// Parses provider local times such as `2025-06-01T10:00:00`
+fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .ok()
+}
+
+/// Filters applied to parsed itineraries before they count towards the result limit
+#[derive(Debug, Default)]
+struct OptionFilters {
+    exclude_self_transfer: bool,
+    /// Drop itineraries whose first leg has any stops
+    nonstop_only: bool,
+    /// Most stops allowed on the first leg; with `nonstop_only` both apply, so
+    /// the stricter of the two wins
+    max_stops: Option<usize>,
+    refundable_only: bool,
+    /// With `refundable_only`, keep fares whose refundability is unknown
+    keep_unknown_refundability: bool,
+    /// Maximum quote age in seconds; quotes of unknown age are kept
+    max_quote_age: Option<u64>,
+    /// Required connection airport code, upper case
+    via: Option<String>,
+    /// Airports itineraries may start and end at, upper case; empty allows any
+    allowed_airports: Vec<String>,
+    /// Airports itineraries may not start, end or connect at, upper case
+    denied_airports: Vec<String>,
+    /// Cabin every segment must be in; segments without cabin data are not checked
+    strict_cabin: Option<String>,
+    /// Highest price and the currency it is given in; fares quoted in another
+    /// currency can't be compared and are kept
+    max_price: Option<(f64, String)>,
+    /// Lowest booking agent rating allowed; unrated agents are kept
+    min_agent_rating: Option<f64>,
+}
+
+impl OptionFilters {
+    /// The first filter that drops `option`, or `None` when it is kept
+    fn rejected_by(&self, option: &FlightOption) -> Option<Filter> {
+        if self.exclude_self_transfer && option.self_transfer {
+            return Some(Filter::SelfTransfer);
+        }
+        if self.nonstop_only && option.stops > 0 {
+            return Some(Filter::Stops);
+        }
+        if self.max_stops.is_some_and(|max| option.stops > max) {
+            return Some(Filter::Stops);
+        }
+        if self.refundable_only && !option.refundable.unwrap_or(self.keep_unknown_refundability) {
+            return Some(Filter::Refundable);
+        }
+        if let (Some(max_age), Some(age)) = (self.max_quote_age, option.quote_age_seconds)
+            && age > max_age
+        {
+            return Some(Filter::QuoteAge);
+        }
+        let endpoints = [&option.origin_airport, &option.destination_airport]
+            .into_iter()
+            .filter(|code| !code.is_empty())
+            .map(|code| code.to_uppercase());
+        // Connections on the way back count as much as those on the way out
+        let return_connections = option
+            .return_leg
+            .iter()
+            .flat_map(|leg| leg.connection_airports.iter().cloned());
+        let connections = option
+            .connection_airports
+            .iter()
+            .cloned()
+            .chain(return_connections);
+        for code in endpoints.chain(connections) {
+            if self.denied_airports.contains(&code) {
+                return Some(Filter::Airports);
+            }
+        }
+        if !self.allowed_airports.is_empty()
+            && [&option.origin_airport, &option.destination_airport]
+                .iter()
+                .any(|code| {
+                    !code.is_empty() && !self.allowed_airports.contains(&code.to_uppercase())
+                })
+        {
+            return Some(Filter::Airports);
+        }
+        if let Some(via) = &self.via
+            && !option.connection_airports.contains(via)
+        {
+            return Some(Filter::Via);
+        }
+        if let Some(cabin) = &self.strict_cabin
+            && option.segment_cabins.iter().any(|c| c != cabin)
+        {
+            return Some(Filter::Cabin);
+        }
+        if let Some((max_price, currency)) = &self.max_price
+            && option.currency.eq_ignore_ascii_case(currency)
+            && option.price > *max_price
+        {
+            return Some(Filter::Price);
+        }
+        if let (Some(min_rating), Some(rating)) = (self.min_agent_rating, option.agent_rating)
+            && rating < min_rating
+        {
+            return Some(Filter::AgentRating);
+        }
+        None
+    }
+}
+
+/// Option filters, for reporting how many options each one dropped
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Filter {
+    SelfTransfer,
+    Stops,
+    Refundable,
+    QuoteAge,
+    Airports,
+    Via,
+    Cabin,
+    Price,
+    AgentRating,
+}
+
+/// Options one list response lost to each filter
+#[derive(Debug, Default, PartialEq)]
+struct FilterDrops {
+    self_transfer: usize,
+    stops: usize,
+    refundable: usize,
+    quote_age: usize,
+    airports: usize,
+    via: usize,
+    cabin: usize,
+    price: usize,
+    agent_rating: usize,
+}
+
+impl FilterDrops {
+    fn add(&mut self, filter: Filter) {
+        let count = match filter {
+            Filter::SelfTransfer => &mut self.self_transfer,
+            Filter::Stops => &mut self.stops,
+            Filter::Refundable => &mut self.refundable,
+            Filter::QuoteAge => &mut self.quote_age,
+            Filter::Airports => &mut self.airports,
+            Filter::Via => &mut self.via,
+            Filter::Cabin => &mut self.cabin,
+            Filter::Price => &mut self.price,
+            Filter::AgentRating => &mut self.agent_rating,
+        };
+        *count += 1;
+    }
+
+    /// Records the counts as an event on the current span, exported as an
+    /// OpenTelemetry span event, to show which filters cut the most results
+    fn record(&self, kept: usize) {
+        info!(
+            name: "options_filtered",
+            kept,
+            self_transfer = self.self_transfer,
+            stops = self.stops,
+            refundable = self.refundable,
+            quote_age = self.quote_age,
+            airports = self.airports,
+            via = self.via,
+            cabin = self.cabin,
+            price = self.price,
+            agent_rating = self.agent_rating,
+            "Filtered flight options"
+        );
+    }
+}
+
+/// Filters a provider's options and carries over its completion status
+fn parse_search_result(
+    results: ProviderResults,
+    filters: &OptionFilters,
+    limit: usize,
+) -> FlightSearchResult {
+    let results_complete = results.complete;
+    let mut options = parse_flight_options(results.options, filters, limit);
+    for option in &mut options {
+        option.price_confidence = PriceConfidence::from_complete(results_complete);
+    }
+    FlightSearchResult {
+        options,
+        results_complete,
+        total_results: results.total_results,
+        ..Default::default()
+    }
+}
+
+/// Parses a raw Skyscanner list response with no filters or result limit, e.g. for replaying captured fixtures
+pub fn parse_response(
+    data: &Value,
+    default_currency: &str,
+) -> Result<FlightSearchResult, FlightSearchError> {
+    Ok(parse_search_result(
+        skyscanner_results(&ListResponse::from_value(data)?, default_currency),
+        &OptionFilters::default(),
+        usize::MAX,
+    ))
+}
+
+/// Keeps at most `limit` of a provider's options passing `filters`
+fn parse_flight_options(
+    options: Vec<FlightOption>,
+    filters: &OptionFilters,
+    limit: usize,
+) -> Vec<FlightOption> {
+    let (flight_options, drops) = filter_flight_options(options, filters, limit);
+    drops.record(flight_options.len());
+    flight_options
+}
+
+/// Parses and filters options, counting what each filter dropped
+fn filter_flight_options(
+    options: Vec<FlightOption>,
+    filters: &OptionFilters,
+    limit: usize,
+) -> (Vec<FlightOption>, FilterDrops) {
+    let mut flight_options = Vec::new();
+    let mut drops = FilterDrops::default();
+    for option in options {
+        // Only push if price is nonzero
+        if option.price > 0.0 {
+            match filters.rejected_by(&option) {
+                Some(filter) => drops.add(filter),
+                None => flight_options.push(option),
+            }
+        }
+        if flight_options.len() >= limit {
+            break;
+        }
+    }
+    (flight_options, drops)
+}
+
+/// Fails with `SchemaMismatch` when more than `max_degraded` of the options parsed
+/// without an airline or a price, which usually means the response schema drifted
+fn check_result_quality(
+    options: &[FlightOption],
+    max_degraded: Option<f64>,
+) -> Result<(), FlightSearchError> {
+    let Some(max_degraded) = max_degraded else {
+        return Ok(());
+    };
+    let total = options.len();
+    let degraded = options
+        .iter()
+        .filter(|option| option.airline == "Unknown Airline" || option.price <= 0.0)
+        .count();
+    if total > 0 && degraded as f64 / total as f64 > max_degraded {
+        error!("{} of {} itineraries could not be parsed", degraded, total);
+        return Err(FlightSearchError::SchemaMismatch(format!(
+            "{} of {} itineraries are missing an airline or price",
+            degraded, total
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn format_duration(minutes: Option<u64>) -> String {
+    minutes
+        .map(|mins| format!("{} hours {} minutes", mins / 60, mins % 60))
+        .unwrap_or_else(|| "Unknown duration".to_string())
+}
+
+/// Divides the fare by the distance flown on every leg, so a round trip counts
+/// the route twice. Guards against a zero distance, e.g. two names for the same airport
+fn price_per_km(option: &FlightOption, distance_km: f64) -> Option<f64> {
+    let legs = if option.return_leg.is_some() {
+        2.0
+    } else {
+        1.0
+    };
+    (distance_km > 0.0).then(|| option.price / (distance_km * legs))
+}
+
+/// Travel dates with their weekdays, e.g. "Departing Sat, Jun 1 · Returning Sat, Jun 8"
+fn trip_dates_line(
+    departure_date: &str,
+    return_date: Option<&str>,
+    labels: &OutputLabels,
+) -> String {
+    let describe = |date: &str| match NaiveDate::parse_from_str(date, DATE_FORMAT) {
+        Ok(date) => format!(
+            "{}, {} {}",
+            labels.weekdays[date.weekday().num_days_from_monday() as usize],
+            labels.months[date.month0() as usize],
+            date.day()
+        ),
+        Err(_) => date.to_string(),
+    };
+    let departing = format!("{} {}", labels.departing, describe(departure_date));
+    match return_date {
+        Some(return_date) => format!(
+            "{} · {} {}",
+            departing,
+            labels.returning,
+            describe(return_date)
+        ),
+        None => departing,
+    }
+}
+
+/// How many itineraries the provider found against how many are shown,
+/// e.g. "Found 214 itineraries, showing 5"
+fn results_count_line(total: u64, shown: usize, labels: &OutputLabels) -> String {
+    format!(
+        "{} {} {} {}",
+        labels.found, total, labels.itineraries_showing, shown
+    )
+}
+
+/// Renders flight options as markdown for the LLM using the given labels
+fn format_flight_options(flight_options: &[FlightOption], labels: &OutputLabels) -> String {
+    let mut output = String::new();
+    output.push_str(&format!("{}\n\n", labels.header));
+    for (i, option) in flight_options.iter().enumerate() {
+        output.push_str(&format_flight_option(i + 1, option, labels));
+    }
+    output
+}
+
+/// Renders flight options in sections by number of stops, numbering across sections
+fn format_grouped_by_stops(flight_options: &[FlightOption], labels: &OutputLabels) -> String {
+    let mut output = String::new();
+    output.push_str(&format!("{}\n", labels.header));
+    let mut current = None;
+    for (i, option) in flight_options.iter().enumerate() {
+        let section = stops_section(option.stops);
+        if current != Some(section) {
+            let title = match section {
+                0 => labels.non_stop,
+                1 => labels.one_stop,
+                _ => labels.two_plus_stops,
+            };
+            output.push_str(&format!("\n### {}\n\n", title));
+            current = Some(section);
+        }
+        output.push_str(&format_flight_option(i + 1, option, labels));
+    }
+    output
+}
+
+/// " (LGW)" after a timestamp, or nothing when the airport is unknown
+fn airport_suffix(code: &str) -> String {
+    if code.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", code)
+    }
+}
+
+/// Renders one numbered flight option as markdown
+fn format_flight_option(number: usize, option: &FlightOption, labels: &OutputLabels) -> String {
+    let mut output = String::new();
+    output.push_str(&format!(
+        "{}. **{}**: {}\n",
+        number, labels.airline, option.airline
+    ));
+    if let Some(reason) = &option.rank_reason {
+        output.push_str(&format!("   - **{}**: {}\n", labels.ranking, reason));
+    }
+    if option.flight_numbers.len() > 1 {
+        output.push_str(&format!(
+            "   - **{}**: {}\n",
+            labels.flight_numbers,
+            option.flight_numbers.join(" → ")
+        ));
+    } else if let Some(operating) = &option.operating_flight_number {
+        output.push_str(&format!(
+            "   - **{}**: {} {}, {} {}\n",
+            labels.flight_number,
+            labels.marketed_as,
+            option.flight_number,
+            labels.operated_as,
+            operating
+        ));
+    } else {
+        output.push_str(&format!(
+            "   - **{}**: {}\n",
+            labels.flight_number, option.flight_number
+        ));
+    }
+    output.push_str(&format!(
+        "   - **{}**: {}{}\n",
+        labels.departure,
+        option.departure,
+        airport_suffix(&option.origin_airport)
+    ));
+    output.push_str(&format!(
+        "   - **{}**: {}{}\n",
+        labels.arrival,
+        option.arrival,
+        airport_suffix(&option.destination_airport)
+    ));
+    output.push_str(&format!(
+        "   - **{}**: {}\n",
+        labels.duration, option.duration
+    ));
+    let stops = if option.stops == 0 {
+        labels.non_stop.to_string()
+    } else {
+        format!("{} {}", option.stops, labels.stop_count)
+    };
+    if option.segment_count > 0 {
+        output.push_str(&format!(
+            "   - **{}**: {} ({} {})\n",
+            labels.stops, stops, option.segment_count, labels.segments
+        ));
+    } else {
+        output.push_str(&format!("   - **{}**: {}\n", labels.stops, stops));
+    }
+    if !option.cabins.is_empty() {
+        output.push_str(&format!(
+            "   - **{}**: {}\n",
+            labels.cabin,
+            option.cabins.join(" / ")
+        ));
+    }
+    output.push_str(&format!(
+        "   - **{}**: {:.2} {}\n",
+        labels.price, option.price, option.currency
+    ));
+    if let Some(price_per_hour) = option.price_per_hour {
+        output.push_str(&format!(
+            "   - **{}**: {:.2} {}\n",
+            labels.price_per_hour, price_per_hour, option.currency
+        ));
+    }
+    if let Some(price_per_km) = option.price_per_km {
+        output.push_str(&format!(
+            "   - **{}**: {:.3} {}\n",
+            labels.price_per_km, price_per_km, option.currency
+        ));
+    }
+    if option.stops > 0
+        && let Some(minutes) = option.min_connection_minutes
+    {
+        output.push_str(&format!(
+            "   - **{}**: {} min\n",
+            labels.min_connection, minutes
+        ));
+    }
+    if let Some(age) = option.quote_age_seconds.filter(|age| *age >= 60) {
+        output.push_str(&format!(
+            "   - **{}**: {} min\n",
+            labels.quote_age,
+            age / 60
+        ));
+    }
+    if let Some(refundable) = option.refundable {
+        output.push_str(&format!(
+            "   - **{}**: {}\n",
+            labels.refundable,
+            if refundable { labels.yes } else { labels.no }
+        ));
+    }
+    if let Some(rating) = option.agent_rating {
+        output.push_str(&format!(
+            "   - **{}**: {:.1}\n",
+            labels.agent_rating, rating
+        ));
+    }
+    if let Some(baggage_fee) = option.baggage_fee {
+        output.push_str(&format!(
+            "   - **{}**: +{:.2} {}\n",
+            labels.checked_bag, baggage_fee, option.currency
+        ));
+    }
+    let mut other_prices: Vec<_> = option
+        .prices
+        .iter()
+        .filter(|(currency, _)| **currency != option.currency)
+        .collect();
+    if !other_prices.is_empty() {
+        other_prices.sort_by(|a, b| a.0.cmp(b.0));
+        let quotes: Vec<String> = other_prices
+            .iter()
+            .map(|(currency, price)| format!("{:.2} {}", price, currency))
+            .collect();
+        output.push_str(&format!(
+            "   - **{}**: {}\n",
+            labels.also_priced,
+            quotes.join(", ")
+        ));
+    }
+    if let Some(leg) = &option.return_leg {
+        output.push_str(&format_return_leg(leg, labels));
+    }
+    if option.price_confidence == PriceConfidence::Preliminary {
+        output.push_str(&format!(
+            "   - **{}**: {}\n",
+            labels.price_confidence, labels.preliminary_price
+        ));
+    }
+    if option.currency_mismatch {
+        output.push_str(&format!(
+            "   - **{}**: {}\n",
+            labels.warning, labels.currency_mismatch
+        ));
+    }
+    if option.self_transfer {
+        output.push_str(&format!(
+            "   - **{}**: {}\n",
+            labels.warning, labels.self_transfer
+        ));
+    }
+    output
+}
+
+/// Renders the inbound leg as a nested section under its option
+fn format_return_leg(leg: &LegDetails, labels: &OutputLabels) -> String {
+    let stops = if leg.stops == 0 {
+        labels.non_stop.to_string()
+    } else {
+        format!("{} {}", leg.stops, labels.stop_count)
+    };
+    let mut lines = vec![
+        format!("   - **{}**:", labels.return_leg),
+        format!("     - **{}**: {}", labels.airline, leg.airline),
+    ];
+    if !leg.flight_numbers.is_empty() {
+        lines.push(format!(
+            "     - **{}**: {}",
+            labels.flight_numbers,
+            leg.flight_numbers.join(" → ")
+        ));
+    }
+    lines.extend([
+        format!("     - **{}**: {}", labels.departure, leg.departure),
+        format!("     - **{}**: {}", labels.arrival, leg.arrival),
+        format!("     - **{}**: {}", labels.duration, leg.duration),
+        format!("     - **{}**: {}", labels.stops, stops),
+    ]);
+    if !leg.cabins.is_empty() {
+        lines.push(format!(
+            "     - **{}**: {}",
+            labels.cabin,
+            leg.cabins.join(" / ")
+        ));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// One-line error object for json and ndjson callers
+fn error_json(error: &FlightSearchError) -> String {
+    json!({ "error": { "code": error.code(), "message": error.to_string() } }).to_string()
+}
+
+/// One JSON object per option per line, for streaming sinks
+fn to_ndjson(flight_options: &[FlightOption]) -> serde_json::Result<String> {
+    let lines = flight_options
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    Ok(lines.join("\n"))
+}
+
+/// Compact summary of the cheapest option for pasting into a message, e.g.
+/// "AUS→BCN Jun 1, cheapest $540 on Iberia, 1 stop", plus its booking link
+fn share_text(flight_options: &[FlightOption], labels: &OutputLabels) -> String {
+    let Some(top) = flight_options
+        .iter()
+        .min_by(|a, b| a.price.total_cmp(&b.price))
+    else {
+        return String::new();
+    };
+    let date = parse_datetime(&top.departure)
+        .map(|departure| {
+            format!(
+                "{} {}",
+                labels.months[departure.month0() as usize],
+                departure.day()
+            )
+        })
+        .unwrap_or_else(|| top.departure.clone());
+    let price = match top.currency.as_str() {
+        "USD" => format!("${:.0}", top.price),
+        "EUR" => format!("€{:.0}", top.price),
+        "GBP" => format!("£{:.0}", top.price),
+        currency => format!("{:.0} {}", top.price, currency),
+    };
+    let stops = match top.stops {
+        0 => labels.non_stop.to_lowercase(),
+        1 => labels.one_stop.to_string(),
+        stops => format!("{} {}", stops, labels.stops_plural),
+    };
+    let mut text = format!(
+        "{}→{} {}, {} {} {} {}, {}",
+        top.origin_airport,
+        top.destination_airport,
+        date,
+        labels.share_cheapest,
+        price,
+        labels.share_on,
+        top.airline,
+        stops
+    );
+    if let Some(url) = &top.booking_url {
+        text.push_str(&format!("\n{}", url));
+    }
+    text
+}
+
+#[instrument(name = "resolve_location", skip(provider, budget, cache))]
+async fn resolve_location(
+    provider: &dyn FlightProvider,
+    query: &str,
+    budget: &RequestBudget,
+    cache: &Mutex<LruCache<String, Location>>,
+) -> Result<Location, FlightSearchError> {
+    // Ids are provider specific, so the provider is part of the key
+    let cache_key = format!("{}:{}", provider.name(), query.trim().to_lowercase());
+    let started = Instant::now();
+    let cached = cache.lock().unwrap().get(&cache_key);
+    if let Some(location) = cached {
+        debug!("Location cache hit for query: {}", query);
+        record_resolve_duration(elapsed_ms(started), true);
+        return Ok(location);
+    }
+    let started = Instant::now();
+    let location = provider.resolve_location(query, budget).await;
+    record_resolve_duration(elapsed_ms(started), false);
+    let location = location?;
+    cache.lock().unwrap().insert(cache_key, location.clone());
+    Ok(location)
+}
+
+/// Cache of resolved locations, bounded by `location_cache_size` and expired after
+/// `location_cache_ttl_secs` when set
+fn location_cache(config: &Config) -> LocationCache {
+    let ttl = config.location_cache_ttl_secs.map(StdDuration::from_secs);
+    Arc::new(Mutex::new(LruCache::with_ttl(
+        config.location_cache_size,
+        ttl,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::i18n::{ENGLISH, SPANISH};
+    use crate::metrics::run_summary;
+    use crate::metrics::{FLIGHT_STATUS_ERROR, PROVIDER_REQUEST_ERROR, tally};
+    use crate::provider::{ProviderFuture, parse_flight_option};
+    use chrono::{TimeZone, Utc};
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn cleanup_test_env() {
+        unsafe { env::remove_var("RAPIDAPI_KEY") };
+    }
+
+    /// Builds a minimal Skyscanner itinerary item with a single leg
+    fn mock_item(airline: &str, flight_number: &str, price: f64) -> Value {
+        json!({
+            "legs": [{
+                "carriers": { "marketing": [{ "name": airline }] },
+                "segments": [{ "flightNumber": flight_number }],
+                "departure": "2025-06-01T10:00:00",
+                "arrival": "2025-06-01T22:30:00",
+                "durationInMinutes": 750,
+                "stopCount": 1
+            }],
+            "pricingOptions": [{ "price": { "amount": price, "currencyCode": "USD" } }]
+        })
+    }
+
+    fn mock_response(items: Vec<Value>) -> Value {
+        json!({ "itineraries": { "buckets": [{ "items": items }] } })
+    }
+
+    /// Clock a month before the 2025-06-01 departures used throughout the tests
+    fn test_clock() -> FixedClock {
+        FixedClock(Utc.with_ymd_and_hms(2025, 5, 1, 12, 0, 0).unwrap())
+    }
+
+    /// Deserializes a mocked JSON value into the typed response model
+    fn typed<T: serde::de::DeserializeOwned>(value: &Value) -> T {
+        serde_json::from_value(value.clone()).unwrap()
+    }
+
+    /// Runs a mocked list response through the Skyscanner parser
+    fn parsed(value: &Value, currency: &str) -> ProviderResults {
+        skyscanner_results(&typed(value), currency)
+    }
+
+    #[test]
+    fn test_flight_search_args_validation() {
+        let tool = FlightSearchTool::new();
+
+        // Test with empty source
+        let args = FlightSearchArgs {
+            source: "".to_string(),
+            destination: "DEL".to_string(),
+            ..Default::default()
+        };
+        let result = tokio::runtime::Runtime::new()
             .unwrap()
             .block_on(tool.call(args));
+        assert!(result.is_err());
 
+        // Test with empty destination
+        let args = FlightSearchArgs {
+            source: "BOM".to_string(),
+            destination: "".to_string(),
+            ..Default::default()
+        };
+        let result = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args));
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_flight_search_tool_definition() {
+        let tool = FlightSearchTool::new();
+        let definition = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.definition("test".to_string()));
+
+        assert_eq!(definition.name, "search_flights");
+        assert!(definition.description.contains("Search for flights"));
+        assert!(definition.parameters.to_string().contains("source"));
+        assert!(definition.parameters.to_string().contains("destination"));
+    }
+
+    #[test]
+    fn test_missing_api_key_error() {
+        cleanup_test_env(); // Ensure no API key is set
+        let tool = FlightSearchTool::new();
+        let args = FlightSearchArgs {
+            source: "BOM".to_string(),
+            destination: "DEL".to_string(),
+            ..Default::default()
+        };
+
+        let result = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_self_transfer_itinerary() {
+        let mut self_transfer = mock_item("Ryanair", "FR1", 80.0);
+        self_transfer["isSelfTransfer"] = json!(true);
+        let data = mock_response(vec![mock_item("Iberia", "IB6", 540.0), self_transfer]);
+
+        let options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        assert_eq!(options.len(), 2);
+        assert!(!options[0].self_transfer);
+        assert!(options[1].self_transfer);
+        let output = format_flight_options(&options, &ENGLISH);
+        assert_eq!(output.matches("Self-transfer itinerary").count(), 1);
+
+        let filters = OptionFilters {
+            exclude_self_transfer: true,
+            ..Default::default()
+        };
+        let options = parse_flight_options(parsed(&data, "USD").options, &filters, 5);
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].airline, "Iberia");
+    }
+
+    #[test]
+    fn test_retry_on_empty() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let responses = [
+            mock_response(vec![]),
+            mock_response(vec![mock_item("Iberia", "IB6", 540.0)]),
+        ];
+
+        let mut calls = 0;
+        let result = runtime
+            .block_on(retry_on_empty(true, StdDuration::ZERO, || {
+                let data = &responses[calls];
+                calls += 1;
+                async move {
+                    Ok(parse_search_result(
+                        parsed(data, "USD"),
+                        &OptionFilters::default(),
+                        5,
+                    ))
+                }
+            }))
+            .unwrap();
+        assert_eq!(calls, 2);
+        assert_eq!(result.options.len(), 1);
+
+        // Disabled toggle concludes after the first empty response
+        let mut calls = 0;
+        let result = runtime
+            .block_on(retry_on_empty(false, StdDuration::ZERO, || {
+                let data = &responses[calls];
+                calls += 1;
+                async move {
+                    Ok(parse_search_result(
+                        parsed(data, "USD"),
+                        &OptionFilters::default(),
+                        5,
+                    ))
+                }
+            }))
+            .unwrap();
+        assert_eq!(calls, 1);
+        assert!(result.options.is_empty());
+    }
+
+    #[test]
+    fn test_merge_currency_results() {
+        let usd = mock_response(vec![
+            mock_item("Iberia", "IB6", 540.0),
+            mock_item("Delta", "DL1", 610.0),
+        ]);
+        let mut eur = mock_response(vec![mock_item("Iberia", "IB6", 495.0)]);
+        eur["itineraries"]["buckets"][0]["items"][0]["pricingOptions"][0]["price"]["currencyCode"] =
+            json!("EUR");
+
+        let filters = OptionFilters::default();
+        let merged = merge_currency_results(vec![
+            parse_search_result(parsed(&usd, "USD"), &filters, 5),
+            parse_search_result(parsed(&eur, "EUR"), &filters, 5),
+        ]);
+
+        let merged = merged.options;
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].prices.get("USD"), Some(&540.0));
+        assert_eq!(merged[0].prices.get("EUR"), Some(&495.0));
+        assert_eq!(merged[1].prices.len(), 1);
+        assert!(format_flight_options(&merged, &ENGLISH).contains("**Also Priced**: 495.00 EUR"));
+    }
+
+    #[test]
+    fn test_merge_currency_results_round_trips() {
+        // Same outbound flight, different ways home
+        let round_trip = |return_flight: &str, price: f64, currency: &str| {
+            let mut item = mock_item("Iberia", "IB6", price);
+            item["legs"].as_array_mut().unwrap().push(json!({
+                "carriers": { "marketing": [{ "name": "Iberia" }] },
+                "segments": [{ "flightNumber": return_flight }],
+                "departure": "2025-06-08T12:00:00",
+                "arrival": "2025-06-08T15:00:00",
+                "durationInMinutes": 600,
+                "stopCount": 0
+            }));
+            item["pricingOptions"][0]["price"]["currencyCode"] = json!(currency);
+            item
+        };
+        let usd = mock_response(vec![
+            round_trip("IB7", 540.0, "USD"),
+            round_trip("IB9", 610.0, "USD"),
+        ]);
+        let eur = mock_response(vec![
+            round_trip("IB9", 560.0, "EUR"),
+            round_trip("IB7", 495.0, "EUR"),
+        ]);
+
+        let filters = OptionFilters::default();
+        let merged = merge_currency_results(vec![
+            parse_search_result(parsed(&usd, "USD"), &filters, 5),
+            parse_search_result(parsed(&eur, "EUR"), &filters, 5),
+        ])
+        .options;
+        assert_eq!(merged.len(), 2);
+        let eur_price = |return_flight: &str| {
+            let option = merged
+                .iter()
+                .find(|o| o.return_leg.as_ref().unwrap().flight_numbers == [return_flight])
+                .unwrap();
+            option.prices.get("EUR").copied()
+        };
+        assert_eq!(eur_price("IB7"), Some(495.0));
+        assert_eq!(eur_price("IB9"), Some(560.0));
+    }
+
+    #[test]
+    fn test_lead_time_advice() {
+        assert_eq!(
+            lead_time_advice(45, &ENGLISH),
+            "Booking 45 days out — typically a good window"
+        );
+        assert_eq!(
+            lead_time_advice(150, &ENGLISH),
+            "Booking 150 days out — earlier than usual; fares often drop closer to departure"
+        );
+        assert_eq!(
+            lead_time_advice(10, &ENGLISH),
+            "Booking 10 days out — fares usually start climbing inside three weeks"
+        );
+        assert_eq!(
+            lead_time_advice(2, &ENGLISH),
+            "Booking 2 days out — last-minute; expect higher fares"
+        );
+        assert_eq!(
+            lead_time_advice(45, &SPANISH),
+            "Reservando con 45 días de antelación — normalmente un buen momento"
+        );
+        assert_eq!(
+            lead_time_advice(-1, &SPANISH),
+            "Reservando con -1 días de antelación — la fecha de salida ya ha pasado"
+        );
+    }
+
+    #[test]
+    fn test_results_complete_status() {
+        let filters = OptionFilters::default();
+        let mut data = mock_response(vec![mock_item("Iberia", "IB6", 540.0)]);
+
+        data["context"] = json!({ "status": "complete" });
+        let result = parse_search_result(parsed(&data, "USD"), &filters, 5);
+        assert!(result.results_complete);
+        assert_eq!(
+            serde_json::to_value(&result).unwrap()["results_complete"],
+            json!(true)
+        );
+
+        data["context"] = json!({ "status": "incomplete" });
+        let result = parse_search_result(parsed(&data, "USD"), &filters, 5);
+        assert!(!result.results_complete);
+        assert_eq!(
+            serde_json::to_value(&result).unwrap()["results_complete"],
+            json!(false)
+        );
+    }
+
+    #[test]
+    fn test_price_confidence() {
+        let filters = OptionFilters::default();
+        let mut data = mock_response(vec![mock_item("Iberia", "IB6", 540.0)]);
+        let confidence = |data: &Value| {
+            let result = parse_search_result(parsed(data, "USD"), &filters, 5);
+            result.options[0].price_confidence
+        };
+
+        data["context"] = json!({ "status": "complete" });
+        assert_eq!(confidence(&data), PriceConfidence::Final);
+        // Responses without a status are treated as complete
+        data["context"] = json!({});
+        assert_eq!(confidence(&data), PriceConfidence::Final);
+        data["context"] = json!({ "status": "incomplete" });
+        assert_eq!(confidence(&data), PriceConfidence::Preliminary);
+
+        let result = parse_search_result(parsed(&data, "USD"), &filters, 5);
+        let json = serde_json::to_value(&result.options[0]).unwrap();
+        assert_eq!(json["price_confidence"], json!("preliminary"));
+        let output = format_flight_options(&result.options, &ENGLISH);
+        assert!(
+            output
+                .contains("**Price Confidence**: Preliminary; re-confirm the fare before booking")
+        );
+    }
+
+    #[test]
+    fn test_request_budget_aborts_fan_out() {
+        let budget = RequestBudget::new(3);
+        // Two resolutions plus two currency searches exceed a cap of three requests
+        let results: Vec<_> = (0..4).map(|_| budget.acquire()).collect();
+        assert!(results[..3].iter().all(|r| r.is_ok()));
+        assert!(matches!(
+            results[3],
+            Err(FlightSearchError::RequestLimitExceeded(3))
+        ));
+        assert_eq!(
+            budget.acquire().unwrap_err().to_string(),
+            "Request limit exceeded: more than 3 requests for one search"
+        );
+    }
+
+    #[test]
+    fn test_nearby_prices() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let location = Location::default();
+        let params = SearchParams {
+            trip_type: TripType::RoundTrip,
+            origin: location.clone(),
+            destination: location,
+            departure_date: NaiveDate::from_ymd_opt(2025, 6, 1).unwrap(),
+            return_date: NaiveDate::from_ymd_opt(2025, 6, 8),
+            cabin: "economy".to_string(),
+            adults: 1,
+            children: 0,
+            infants: 0,
+            market: "US".to_string(),
+            currency: "USD".to_string(),
+            locale: "en-US".to_string(),
+        };
+        let prices = HashMap::from([
+            (NaiveDate::from_ymd_opt(2025, 5, 31).unwrap(), 520.0),
+            (NaiveDate::from_ymd_opt(2025, 6, 2).unwrap(), 480.0),
+        ]);
+
+        let (before, after) = runtime
+            .block_on(cheapest_on_nearby_days(|offset| {
+                let price = prices[&params.shifted(offset).departure_date];
+                async move {
+                    let data = mock_response(vec![
+                        mock_item("Iberia", "IB6", price),
+                        mock_item("Delta", "DL1", price + 100.0),
+                    ]);
+                    Ok(parse_search_result(
+                        parsed(&data, "USD"),
+                        &OptionFilters::default(),
+                        5,
+                    ))
+                }
+            }))
+            .unwrap();
+
+        assert_eq!(
+            nearby_price_line("USD", before, Some(540.0), after, &ENGLISH),
+            "Day before: 520.00 USD, selected: 540.00 USD, day after: 480.00 USD"
+        );
+        assert_eq!(
+            nearby_price_line("USD", before, None, after, &SPANISH),
+            "Día anterior: 520.00 USD, fecha elegida: n/d, día siguiente: 480.00 USD"
+        );
+        assert_eq!(
+            params.shifted(1).return_date,
+            NaiveDate::from_ymd_opt(2025, 6, 9)
+        );
+    }
+
+    #[test]
+    fn test_cheaper_date_suggestion() {
+        let departure = NaiveDate::from_ymd_opt(2025, 6, 1).unwrap();
+        let today = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+        let window = PriceCalendarWindow {
+            days_before: CHEAPER_DATE_PROBE_DAYS,
+            days_after: CHEAPER_DATE_PROBE_DAYS,
+        };
+        let prices = HashMap::from([
+            (-2, Some(590.0)),
+            (-1, Some(520.0)),
+            (1, None),
+            (2, Some(420.0)),
+        ]);
+        let calendar = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(price_calendar(
+                departure,
+                today,
+                window,
+                Some(540.0),
+                |offset| {
+                    let price = prices[&offset];
+                    async move {
+                        let items = price
+                            .map(|price| vec![mock_item("Iberia", "IB6", price)])
+                            .unwrap_or_default();
+                        Ok(parse_search_result(
+                            parsed(&mock_response(items), "USD"),
+                            &OptionFilters::default(),
+                            5,
+                        ))
+                    }
+                },
+            ))
+            .unwrap();
+
+        let suggestion = |calendar| {
+            cheaper_date(calendar, departure, 540.0).map(|c| cheaper_date_line(&c, "USD", &ENGLISH))
+        };
+        assert_eq!(
+            suggestion(&calendar).as_deref(),
+            Some("Flying 2 days later is 120.00 USD cheaper (420.00 USD on 2025-06-03)")
+        );
+        // Saving 20 of 540 isn't worth another date
+        let mut calendar = calendar;
+        calendar.insert("2025-06-03".to_string(), Some(600.0));
+        assert_eq!(suggestion(&calendar), None);
+        calendar.insert("2025-05-31".to_string(), Some(470.0));
+        assert_eq!(
+            suggestion(&calendar).as_deref(),
+            Some("Flying 1 day earlier is 70.00 USD cheaper (470.00 USD on 2025-05-31)")
+        );
+        let cheaper = cheaper_date(&calendar, departure, 540.0).unwrap();
+        assert_eq!(
+            cheaper_date_line(&cheaper, "USD", &SPANISH),
+            "Volar 1 día antes sale 70.00 USD más barato (470.00 USD el 2025-05-31)"
+        );
+    }
+
+    #[test]
+    fn test_resolve_region() {
+        assert_eq!(
+            resolve_region(Some("es-ES"), None, None, None),
+            RegionSettings {
+                market: "ES".to_string(),
+                currency: "EUR".to_string(),
+                locale: "es-ES".to_string(),
+            }
+        );
+        assert_eq!(
+            resolve_region(None, None, None, None),
+            RegionSettings {
+                market: "US".to_string(),
+                currency: "USD".to_string(),
+                locale: "en-US".to_string(),
+            }
+        );
+        // Explicit fields override the profile individually
+        assert_eq!(
+            resolve_region(Some("es-ES"), None, Some("USD".to_string()), None),
+            RegionSettings {
+                market: "ES".to_string(),
+                currency: "USD".to_string(),
+                locale: "es-ES".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_multi_segment_flight_numbers() {
+        let mut item = mock_item("American Airlines", "AA100", 610.0);
+        item["legs"][0]["segments"] = json!([
+            { "flightNumber": "AA100" },
+            { "flightNumber": "AA6350" }
+        ]);
+
+        let option = parse_flight_option(&typed(&item), "USD");
+        assert_eq!(option.flight_number, "AA100");
+        assert_eq!(option.flight_numbers, vec!["AA100", "AA6350"]);
+        assert!(
+            format_flight_options(&[option], &ENGLISH)
+                .contains("**Flight Numbers**: AA100 → AA6350")
+        );
+    }
+
+    #[test]
+    fn test_format_in_second_language() {
+        let data = mock_response(vec![mock_item("Iberia", "IB6", 540.0)]);
+        let options =
+            parse_flight_options(parsed(&data, "EUR").options, &OptionFilters::default(), 5);
+        let output = format_flight_options(&options, &SPANISH);
+
+        assert!(output.starts_with("Estas son algunas opciones de vuelo:"));
+        assert!(output.contains("1. **Aerolínea**: Iberia"));
+        assert!(output.contains("**Escalas**: 1 escala(s)"));
+        assert!(output.contains("**Precio**: 540.00 USD"));
+        assert!(!output.contains("Airline"));
+    }
+
+    #[test]
+    fn test_price_per_hour_and_value_sort() {
+        let mut slow = mock_item("Delta", "DL1", 500.0);
+        slow["legs"][0]["durationInMinutes"] = json!(1000);
+        let mut fast = mock_item("Iberia", "IB6", 450.0);
+        fast["legs"][0]["durationInMinutes"] = json!(600);
+        let mut unknown = mock_item("Level", "LV1", 100.0);
+        unknown["legs"][0]["durationInMinutes"] = json!(0);
+        let data = mock_response(vec![unknown, slow, fast]);
+
+        let mut options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        assert_eq!(options[0].price_per_hour, None);
+        assert!((options[1].price_per_hour.unwrap() - 30.0).abs() < 1e-9);
+        assert_eq!(options[2].price_per_hour, Some(45.0));
+
+        sort_options(&mut options, SortBy::Value);
+        let airlines: Vec<_> = options.iter().map(|o| o.airline.as_str()).collect();
+        assert_eq!(airlines, vec!["Delta", "Iberia", "Level"]);
+    }
+
+    #[test]
+    fn test_price_calendar() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let departure = NaiveDate::from_ymd_opt(2025, 6, 1).unwrap();
+        let today = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+        let window = PriceCalendarWindow {
+            days_before: 1,
+            days_after: 2,
+        };
+
+        let calendar = runtime
+            .block_on(price_calendar(
+                departure,
+                today,
+                window,
+                Some(540.0),
+                |offset| {
+                    let items = match offset {
+                        -1 => vec![mock_item("Iberia", "IB6", 520.0)],
+                        1 => vec![
+                            mock_item("Delta", "DL1", 610.0),
+                            mock_item("Iberia", "IB6", 480.0),
+                        ],
+                        _ => vec![],
+                    };
+                    async move {
+                        Ok(parse_search_result(
+                            parsed(&mock_response(items), "USD"),
+                            &OptionFilters::default(),
+                            5,
+                        ))
+                    }
+                },
+            ))
+            .unwrap();
+
+        let expected: BTreeMap<String, Option<f64>> = [
+            ("2025-05-31", Some(520.0)),
+            ("2025-06-01", Some(540.0)),
+            ("2025-06-02", Some(480.0)),
+            ("2025-06-03", None),
+        ]
+        .into_iter()
+        .map(|(date, price)| (date.to_string(), price))
+        .collect();
+        assert_eq!(calendar, expected);
+        assert_eq!(
+            format_price_calendar(&calendar, "2025-06-01", "USD", &ENGLISH),
+            "Price calendar:\n  2025-05-31: 520.00 USD\n* 2025-06-01: 540.00 USD\n  2025-06-02: 480.00 USD\n  2025-06-03: no flights\n"
+        );
+        assert_eq!(
+            format_price_calendar(&calendar, "2025-06-01", "USD", &SPANISH),
+            "Calendario de precios:\n  2025-05-31: 520.00 USD\n* 2025-06-01: 540.00 USD\n  2025-06-02: 480.00 USD\n  2025-06-03: sin vuelos\n"
+        );
+
+        // Dates already gone are never searched
+        let today = NaiveDate::from_ymd_opt(2025, 5, 31).unwrap();
+        let window = PriceCalendarWindow {
+            days_before: 3,
+            days_after: 0,
+        };
+        let calendar = runtime
+            .block_on(price_calendar(departure, today, window, None, |offset| {
+                assert!(offset >= -1);
+                async move { Ok(FlightSearchResult::default()) }
+            }))
+            .unwrap();
+        assert_eq!(
+            calendar.keys().collect::<Vec<_>>(),
+            vec!["2025-05-31", "2025-06-01"]
+        );
+    }
+
+    #[test]
+    fn test_fallback_cabins_follow_configured_order() {
+        let order: Vec<String> = ["economy", "premium_economy", "business", "first"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            fallback_cabins(&order, "business"),
+            vec!["premium_economy", "economy"]
+        );
+        assert!(fallback_cabins(&order, "economy").is_empty());
+        assert!(fallback_cabins(&order, "unknown").is_empty());
+
+        // Inserted cabins take part in the step-down
+        let order: Vec<String> = ["economy", "economy_plus", "business"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            fallback_cabins(&order, "business"),
+            vec!["economy_plus", "economy"]
+        );
+    }
+
+    #[test]
+    fn test_default_departure_date_with_fixed_clock() {
+        let clock = FixedClock(Utc.with_ymd_and_hms(2025, 5, 1, 12, 0, 0).unwrap());
+        assert_eq!(default_departure_date(&clock), "2025-05-31");
+    }
+
+    #[test]
+    fn test_baggage_fee() {
+        let mut item = mock_item("Spirit", "NK1", 89.0);
+        item["pricingOptions"][0]["baggageFee"] = json!({ "amount": 35.0 });
+        let option = parse_flight_option(&typed(&item), "USD");
+        assert_eq!(option.baggage_fee, Some(35.0));
+        assert!(format_flight_options(&[option], &ENGLISH).contains("**Checked Bag**: +35.00 USD"));
+
+        let option = parse_flight_option(&typed(&mock_item("Iberia", "IB6", 540.0)), "USD");
+        assert_eq!(option.baggage_fee, None);
+        assert!(!format_flight_options(&[option], &ENGLISH).contains("Checked Bag"));
+    }
+
+    #[test]
+    fn test_quote_age_filter() {
+        let mut stale = mock_item("Delta", "DL1", 480.0);
+        stale["pricingOptions"][0]["quoteAge"] = json!(7200);
+        let mut fresh = mock_item("Iberia", "IB6", 540.0);
+        fresh["pricingOptions"][0]["quoteAge"] = json!(300);
+        let unknown = mock_item("United", "UA1", 600.0);
+        let data = mock_response(vec![stale, fresh, unknown]);
+
+        let options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        assert_eq!(options[0].quote_age_seconds, Some(7200));
+        assert!(format_flight_options(&options[..1], &ENGLISH).contains("**Quote Age**: 120 min"));
+
+        let filters = OptionFilters {
+            max_quote_age: Some(3600),
+            ..Default::default()
+        };
+        let options = parse_flight_options(parsed(&data, "USD").options, &filters, 5);
+        let airlines: Vec<_> = options.iter().map(|o| o.airline.as_str()).collect();
+        assert_eq!(airlines, vec!["Iberia", "United"]);
+    }
+
+    /// Provider without optional features that serves canned options
+    #[derive(Debug, Default)]
+    struct BareProvider {
+        options: Vec<FlightOption>,
+        /// Provider calls of any kind, shared so tests can read it after boxing
+        calls: Arc<AtomicUsize>,
+        /// Cabins whose searches fail with an API error
+        failing_cabins: Vec<&'static str>,
+    }
+
+    impl FlightProvider for BareProvider {
+        fn name(&self) -> &'static str {
+            "bare"
+        }
+
+        fn capabilities(&self) -> crate::provider::ProviderCapabilities {
+            Default::default()
+        }
+
+        fn resolve_location<'a>(
+            &'a self,
+            query: &'a str,
+            _budget: &'a RequestBudget,
+        ) -> ProviderFuture<'a, Location> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                if query == "fake-nowhere" {
+                    return Err(FlightSearchError::InvalidResponse(
+                        "No valid airport found in auto-complete response".to_string(),
+                    ));
+                }
+                Ok(Location {
+                    sky_id: query.to_uppercase(),
+                    entity_id: format!("{}-id", query),
+                    metro_name: None,
+                    coordinates: None,
+                })
+            })
+        }
+
+        fn search<'a>(
+            &'a self,
+            params: &'a SearchParams,
+            _budget: &'a RequestBudget,
+        ) -> ProviderFuture<'a, ProviderResults> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(params.origin.entity_id, "fake-src-id");
+            // One-way searches carry no return date
+            assert_eq!(
+                params.return_date.is_some(),
+                params.trip_type == TripType::RoundTrip
+            );
+            Box::pin(async move {
+                if self.failing_cabins.contains(&params.cabin.as_str()) {
+                    return Err(FlightSearchError::ApiError("HTTP 500".to_string()));
+                }
+                Ok(ProviderResults {
+                    options: self.options.clone(),
+                    complete: true,
+                    total_results: None,
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_unsupported_feature_is_rejected() {
+        let args = FlightSearchArgs {
+            price_calendar: Some(PriceCalendarWindow::default()),
+            ..Default::default()
+        };
+        let err = check_capabilities(&args, &BareProvider::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Current provider (bare) doesn't support price_calendar"
+        );
+        let skyscanner = SkyscannerProvider::from_config(&Config::default());
+        assert!(check_capabilities(&args, &skyscanner).is_ok());
+        assert!(check_capabilities(&FlightSearchArgs::default(), &BareProvider::default()).is_ok());
+    }
+
+    #[test]
+    fn test_codeshare_operating_flight_number() {
+        let mut codeshare = mock_item("American Airlines", "AA123", 450.0);
+        codeshare["legs"][0]["segments"][0]["operatingFlightNumber"] = json!("BA456");
+        let mut own_metal = mock_item("Iberia", "IB6", 500.0);
+        own_metal["legs"][0]["segments"][0]["operatingFlightNumber"] = json!("IB6");
+        let data = mock_response(vec![codeshare, own_metal]);
+
+        let options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        assert_eq!(options[0].operating_flight_number.as_deref(), Some("BA456"));
+        assert_eq!(options[1].operating_flight_number, None);
+
+        let output = format_flight_options(&options, &ENGLISH);
+        assert!(output.contains("**Flight Number**: marketed as AA123, operated as BA456"));
+        assert!(output.contains("**Flight Number**: IB6\n"));
+    }
+
+    #[test]
+    fn test_sort_by_departure() {
+        let mut late = mock_item("Delta", "DL1", 480.0);
+        late["legs"][0]["departure"] = json!("2025-06-01T18:45:00");
+        let mut unknown = mock_item("United", "UA1", 300.0);
+        unknown["legs"][0]["departure"] = json!("evening");
+        let mut early = mock_item("Iberia", "IB6", 540.0);
+        early["legs"][0]["departure"] = json!("2025-06-01T06:15:00");
+        let mut next_day = mock_item("Vueling", "VY1", 200.0);
+        next_day["legs"][0]["departure"] = json!("2025-06-02T05:00:00");
+        let data = mock_response(vec![late, unknown, early, next_day]);
+
+        let mut options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        sort_options(&mut options, SortBy::parse("departure").unwrap());
+        let airlines: Vec<_> = options.iter().map(|o| o.airline.as_str()).collect();
+        assert_eq!(airlines, vec!["Iberia", "Delta", "Vueling", "United"]);
+    }
+
+    #[test]
+    fn test_sort_by_price_duration_stops() {
+        let mut long = mock_item("Delta", "DL1", 300.0);
+        long["legs"][0]["durationInMinutes"] = json!(900);
+        let mut nonstop = mock_item("Iberia", "IB6", 540.0);
+        nonstop["legs"][0]["durationInMinutes"] = json!(480);
+        nonstop["legs"][0]["stopCount"] = json!(0);
+        let mut unknown = mock_item("United", "UA1", 450.0);
+        unknown["legs"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("durationInMinutes");
+        let data = typed(&mock_response(vec![nonstop, unknown, long]));
+        let order = |sort_by: &str| {
+            let mut options = parse_flight_options(
+                skyscanner_results(&data, "USD").options,
+                &OptionFilters::default(),
+                5,
+            );
+            sort_options(&mut options, SortBy::parse(sort_by).unwrap());
+            options
+                .into_iter()
+                .map(|o| o.flight_number)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(order("price"), vec!["DL1", "UA1", "IB6"]);
+        assert_eq!(order("duration"), vec!["IB6", "DL1", "UA1"]);
+        assert_eq!(order("stops"), vec!["IB6", "DL1", "UA1"]);
+        assert!(SortBy::parse("fastest").is_err());
+    }
+
+    #[test]
+    fn test_call_returns_cheapest_by_default() {
+        let items = [480.0, 220.0, 610.0, 150.0]
+            .iter()
+            .enumerate()
+            .map(|(i, price)| mock_item("Delta", &format!("DL{}", i), *price))
+            .collect();
+        let provider = BareProvider {
+            options: parsed(&mock_response(items), "USD").options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            max_results: Some(2),
+            ..Default::default()
+        };
+        let output = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args))
+            .unwrap();
+        let cheapest = output.find("150.00 USD").unwrap();
+        assert!(cheapest < output.find("220.00 USD").unwrap());
+        assert!(!output.contains("480.00 USD"));
+    }
+
+    #[test]
+    fn test_sorting_sees_options_past_the_limit() {
+        let items = (1..=7)
+            .map(|i| mock_item("Delta", &format!("DL{}", i), 400.0 + i as f64))
+            .collect();
+        // The shortest flight sits in a later bucket, past the first five parsed
+        let mut fastest = mock_item("Iberia", "IB6", 900.0);
+        fastest["legs"][0]["durationInMinutes"] = json!(420);
+        let mut response = mock_response(items);
+        response["itineraries"]["buckets"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "items": [fastest] }));
+        let provider = BareProvider {
+            options: parsed(&response, "USD").options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            sort_by: Some("duration".to_string()),
+            ..Default::default()
+        };
+        let output = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args))
+            .unwrap();
+        assert!(output.contains("1. **Airline**: Iberia"));
+        assert_eq!(output.matches("**Airline**").count(), 5);
+    }
+
+    #[test]
+    fn test_call_with_injected_provider() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![mock_item("Iberia", "IB6", 540.0)]),
+                "USD",
+            )
+            .options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            ..Default::default()
+        };
+        let output = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args))
+            .unwrap();
+        assert!(output.contains("**Airline**: Iberia"));
+        assert!(output.contains("540.00 USD"));
+    }
+
+    #[test]
+    fn test_metro_area_location() {
+        let autocomplete = json!({
+            "inputSuggest": [
+                { "navigation": { "relevantFlightParams": {
+                    "skyId": "LHR", "entityId": "95565050",
+                    "flightPlaceType": "AIRPORT", "localizedName": "London Heathrow"
+                } } },
+                { "navigation": { "relevantFlightParams": {
+                    "skyId": "LOND", "entityId": "27544008",
+                    "flightPlaceType": "CITY", "localizedName": "London"
+                } } }
+            ]
+        });
+        let london = crate::provider::parse_location(&autocomplete, "london", true).unwrap();
+        assert_eq!(london.sky_id, "LOND");
+        assert_eq!(london.entity_id, "27544008");
+        // An airport query keeps the airport
+        let heathrow = crate::provider::parse_location(&autocomplete, "LHR", true).unwrap();
+        assert_eq!(heathrow.sky_id, "LHR");
+        assert_eq!(heathrow.metro_name, None);
+
+        assert_eq!(
+            metro_note(&[&heathrow, &london], &ENGLISH).as_deref(),
+            Some("Metro-area search: London (all airports)")
+        );
+        assert_eq!(metro_note(&[&heathrow], &ENGLISH), None);
+    }
+
+    #[test]
+    fn test_segment_count() {
+        let mut item = mock_item("Turkish Airlines", "TK34", 720.0);
+        item["legs"][0]["stopCount"] = json!(2);
+        item["legs"][0]["segments"] = json!([
+            { "flightNumber": "TK34" },
+            { "flightNumber": "TK1853" },
+            { "flightNumber": "TK2412" }
+        ]);
+
+        let option = parse_flight_option(&typed(&item), "USD");
+        assert_eq!(option.segment_count, 3);
+        assert!(
+            format_flight_options(&[option], &ENGLISH)
+                .contains("**Stops**: 2 stop(s) (3 segments)")
+        );
+
+        let mut item = mock_item("Iberia", "IB6", 540.0);
+        item["legs"][0]["segments"] = json!([]);
+        assert_eq!(parse_flight_option(&typed(&item), "USD").segment_count, 0);
+    }
+
+    #[test]
+    fn test_one_per_airline() {
+        let data = mock_response(vec![
+            mock_item("Delta", "DL1", 520.0),
+            mock_item("Iberia", "IB6", 610.0),
+            mock_item("Delta", "DL3", 480.0),
+            mock_item("Iberia", "IB8", 590.0),
+            mock_item("United", "UA1", 500.0),
+            mock_item("Delta", "DL5", 700.0),
+        ]);
+        let options = parse_flight_options(
+            parsed(&data, "USD").options,
+            &OptionFilters::default(),
+            usize::MAX,
+        );
+
+        let kept: Vec<_> = cheapest_per_airline(options)
+            .into_iter()
+            .map(|o| (o.flight_number, o.price))
+            .collect();
+        assert_eq!(
+            kept,
+            vec![
+                ("DL3".to_string(), 480.0),
+                ("UA1".to_string(), 500.0),
+                ("IB8".to_string(), 590.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_via_connection_filter() {
+        let via_doh = |airline: &str, number: &str, price: f64| {
+            let mut item = mock_item(airline, number, price);
+            item["legs"][0]["segments"] = json!([
+                { "flightNumber": number, "destination": { "displayCode": "DOH" } },
+                { "flightNumber": number, "destination": { "displayCode": "BCN" } }
+            ]);
+            item
+        };
+        let mut via_ist = mock_item("Turkish Airlines", "TK34", 650.0);
+        via_ist["legs"][0]["segments"] = json!([
+            { "flightNumber": "TK34", "destination": { "displayCode": "IST" } },
+            { "flightNumber": "TK1853", "destination": { "displayCode": "BCN" } }
+        ]);
+        let mut direct_to_doh = mock_item("Iberia", "IB6", 540.0);
+        direct_to_doh["legs"][0]["segments"] =
+            json!([{ "flightNumber": "IB6", "destination": { "displayCode": "DOH" } }]);
+        let data = mock_response(vec![
+            via_doh("Qatar Airways", "QR1", 700.0),
+            via_ist,
+            direct_to_doh,
+            via_doh("Qatar Airways", "QR3", 720.0),
+        ]);
+
+        let filters = OptionFilters {
+            via: Some("DOH".to_string()),
+            ..Default::default()
+        };
+        let options = parse_flight_options(parsed(&data, "USD").options, &filters, 5);
+        let numbers: Vec<_> = options.iter().map(|o| o.flight_number.as_str()).collect();
+        assert_eq!(numbers, vec!["QR1", "QR3"]);
+        assert_eq!(options[0].connection_airports, vec!["DOH"]);
+    }
+
+    #[test]
+    fn test_denylisted_connection_is_filtered() {
+        let via = |number: &str, hub: &str| {
+            let mut item = mock_item("Delta", number, 500.0);
+            item["legs"][0]["segments"] = json!([
+                { "flightNumber": number, "destination": { "displayCode": hub } },
+                { "flightNumber": number, "destination": { "displayCode": "BCN" } }
+            ]);
+            item
+        };
+        let data = typed(&mock_response(vec![via("DL1", "SVO"), via("DL2", "JFK")]));
+        let filters = OptionFilters {
+            denied_airports: vec!["SVO".to_string()],
+            ..Default::default()
+        };
+        let options = parse_flight_options(skyscanner_results(&data, "USD").options, &filters, 5);
+        let numbers: Vec<_> = options.iter().map(|o| o.flight_number.as_str()).collect();
+        assert_eq!(numbers, vec!["DL2"]);
+
+        // A round trip connecting through a denied airport only on the way back
+        let returning_via = |number: &str, hub: &str| {
+            let mut item = mock_item("Delta", number, 500.0);
+            item["legs"].as_array_mut().unwrap().push(json!({
+                "segments": [
+                    { "flightNumber": number, "destination": { "displayCode": hub } },
+                    { "flightNumber": number, "destination": { "displayCode": "JFK" } }
+                ]
+            }));
+            item
+        };
+        let data = typed(&mock_response(vec![
+            returning_via("DL3", "SVO"),
+            returning_via("DL4", "LHR"),
+        ]));
+        let options = parse_flight_options(skyscanner_results(&data, "USD").options, &filters, 5);
+        let numbers: Vec<_> = options.iter().map(|o| o.flight_number.as_str()).collect();
+        assert_eq!(numbers, vec!["DL4"]);
+        assert_eq!(
+            options[0].return_leg.as_ref().unwrap().connection_airports,
+            vec!["LHR"]
+        );
+    }
+
+    #[test]
+    fn test_destination_outside_allowlist_is_rejected() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![mock_item("Iberia", "IB6", 540.0)]),
+                "USD",
+            )
+            .options,
+            ..Default::default()
+        };
+        let config = Config {
+            airport_allowlist: vec!["FAKE-SRC".to_string()],
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), config);
+        let args = FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            ..Default::default()
+        };
+        let err = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid argument: 'fake-dst' resolves to FAKE-DST, which is not on the airport allowlist"
+        );
+
+        let config = Config {
+            airport_denylist: vec!["FAKE-SRC".to_string()],
+            ..Default::default()
+        };
+        let location = Location {
+            sky_id: "fake-src".to_string(),
+            ..Default::default()
+        };
+        let err = check_airport_lists(&location, "fake-src", &config).unwrap_err();
+        assert_eq!(err.code(), "ERR_INVALID_ARGUMENT");
+        assert!(check_airport_lists(&location, "fake-src", &Config::default()).is_ok());
+
+        // Entity-only locations are checked by their entity id rather than skipped
+        let entity_only = Location {
+            entity_id: "128668889".to_string(),
+            ..Default::default()
+        };
+        let config = Config {
+            airport_denylist: vec!["128668889".to_string()],
+            ..Default::default()
+        };
+        assert!(check_airport_lists(&entity_only, "Reus", &config).is_err());
+        let config = Config {
+            airport_allowlist: vec!["BCN".to_string()],
+            ..Default::default()
+        };
+        let err = check_airport_lists(&entity_only, "Reus", &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid argument: 'Reus' resolves to 128668889, which is not on the airport allowlist"
+        );
+    }
+
+    #[test]
+    fn test_fare_breakdown() {
+        let mut item = mock_item("Iberia", "IB6", 540.0);
+        item["pricingOptions"][0]["fareBreakdown"] =
+            json!({ "base": 380.0, "taxes": 112.5, "carrierFees": 47.5 });
+        let option = parse_flight_option(&typed(&item), "USD");
+        assert_eq!(
+            option.fare_breakdown,
+            Some(FareBreakdown {
+                base: 380.0,
+                taxes: 112.5,
+                carrier_fees: 47.5
+            })
+        );
+        let json = serde_json::to_value(&option).unwrap();
+        assert_eq!(json["fare_breakdown"]["taxes"], json!(112.5));
+
+        // Markdown keeps showing only the total
+        let output = format_flight_options(&[option], &ENGLISH);
+        assert!(output.contains("540.00 USD"));
+        assert!(!output.contains("380.00"));
+
+        let option = parse_flight_option(&typed(&mock_item("Delta", "DL1", 480.0)), "USD");
+        assert_eq!(option.fare_breakdown, None);
+        assert!(
+            serde_json::to_value(&option)
+                .unwrap()
+                .get("fare_breakdown")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_premium_cabin_tolerance() {
+        let order: Vec<String> = ["economy", "premium_economy", "business", "first"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            premium_cabins(&order, "premium_economy"),
+            vec!["business", "first"]
+        );
+        assert!(premium_cabins(&order, "first").is_empty());
+
+        let quote = |cabin: &str, price| CabinPrice {
+            cabin: cabin.to_string(),
+            price,
+            unavailable: false,
+        };
+        let economy = quote("economy", Some(500.0));
+        let upgrades = vec![
+            quote("premium_economy", Some(720.0)),
+            quote("business", Some(2400.0)),
+            quote("first", None),
+        ];
+        let comparison = compare_cabin_prices(economy.clone(), upgrades.clone(), Some(1.5));
+        let cabins: Vec<_> = comparison.iter().map(|c| c.cabin.as_str()).collect();
+        assert_eq!(cabins, vec!["economy", "premium_economy"]);
+        assert_eq!(
+            format_cabin_comparison(&comparison, "USD", &ENGLISH),
+            "Cabin comparison:\n- economy: 500.00 USD\n- premium_economy: 720.00 USD (1.4x)\n"
+        );
+        assert_eq!(
+            format_cabin_comparison(&comparison, "USD", &SPANISH),
+            "Comparativa de cabinas:\n- economy: 500.00 USD\n- premium_economy: 720.00 USD (1.4x)\n"
+        );
+
+        // Without a tolerance every priced cabin is kept
+        let comparison = compare_cabin_prices(economy.clone(), upgrades, None);
+        assert_eq!(comparison.len(), 3);
+
+        // A cabin whose search failed stays listed, without a price
+        let failed = CabinPrice {
+            unavailable: true,
+            ..quote("first", None)
+        };
+        let comparison = compare_cabin_prices(economy, vec![failed], Some(1.5));
+        assert_eq!(
+            format_cabin_comparison(&comparison, "USD", &ENGLISH),
+            "Cabin comparison:\n- economy: 500.00 USD\n- first: price unavailable\n"
+        );
+        assert!(
+            format_cabin_comparison(&comparison, "USD", &SPANISH)
+                .ends_with("- first: precio no disponible\n")
+        );
+    }
+
+    #[test]
+    fn test_compare_cabins_survives_failed_cabin() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![mock_item("Delta", "DL1", 620.0)]),
+                "USD",
+            )
+            .options,
+            failing_cabins: vec!["first"],
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            compare_cabins: Some(true),
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let output = runtime.block_on(tool.call(args)).unwrap();
+        assert!(output.contains("- business: 620.00 USD (1.0x)\n"));
+        assert!(output.contains("- first: price unavailable\n"));
+    }
+
+    #[test]
+    fn test_failed_search_counted_once() {
+        let provider = BareProvider {
+            failing_cabins: vec!["economy"],
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = |max_price| FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            max_price,
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        // Rejected before any provider request
+        tally::reset();
+        assert!(runtime.block_on(tool.call(args(Some(-1.0)))).is_err());
+        assert_eq!(tally::count(FLIGHT_STATUS_ERROR), 1);
+        // Failed at the provider; request failures are the provider's to count
+        tally::reset();
+        assert!(runtime.block_on(tool.call(args(None))).is_err());
+        assert_eq!(tally::count(FLIGHT_STATUS_ERROR), 1);
+        assert_eq!(tally::count(PROVIDER_REQUEST_ERROR), 0);
+    }
+
+    #[test]
+    fn test_share_text() {
+        let mut cheapest = mock_item("Iberia", "IB6", 540.0);
+        cheapest["legs"][0]["origin"] = json!({ "displayCode": "AUS" });
+        cheapest["legs"][0]["destination"] = json!({ "displayCode": "BCN" });
+        cheapest["pricingOptions"][0]["items"] = json!([{ "url": "https://www.skyscanner.net/transport_deeplink/4.0/US/en-US/USD/iber/2/13416.9772" }]);
+        let data = mock_response(vec![mock_item("Delta", "DL1", 610.0), cheapest]);
+        let options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+
+        assert_eq!(
+            share_text(&options, &ENGLISH),
+            "AUS→BCN Jun 1, cheapest $540 on Iberia, 1 stop\n\
+             https://www.skyscanner.net/transport_deeplink/4.0/US/en-US/USD/iber/2/13416.9772"
+        );
+        assert!(
+            share_text(&options, &SPANISH)
+                .starts_with("AUS→BCN jun 1, mejor precio $540 con Iberia, 1 escala\n")
+        );
+        assert_eq!(share_text(&[], &ENGLISH), "");
+    }
+
+    #[test]
+    fn test_mixed_currency_results_are_grouped() {
+        let mut euro = mock_item("Vueling", "VY1", 90.0);
+        euro["pricingOptions"][0]["price"]["currencyCode"] = json!("EUR");
+        let data = mock_response(vec![
+            mock_item("Delta", "DL1", 520.0),
+            euro,
+            mock_item("Iberia", "IB6", 480.0),
+        ]);
+        let mut options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        sort_options(&mut options, SortBy::Value);
+        group_by_currency(&mut options, "USD");
+
+        let order: Vec<_> = options
+            .iter()
+            .map(|o| (o.flight_number.as_str(), o.currency_mismatch))
+            .collect();
+        assert_eq!(order, vec![("IB6", false), ("DL1", false), ("VY1", true)]);
+        let output = format_flight_options(&options, &ENGLISH);
+        assert_eq!(output.matches(ENGLISH.currency_mismatch).count(), 1);
+    }
+
+    #[test]
+    fn test_include_timing() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![mock_item("Iberia", "IB6", 540.0)]),
+                "USD",
+            )
+            .options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = |include_timing| FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            response_format: Some("json".to_string()),
+            include_timing: Some(include_timing),
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let output = runtime.block_on(tool.call(args(true))).unwrap();
+        let json: Value = serde_json::from_str(&output).unwrap();
+        let latency = json["search_latency_ms"].as_u64().unwrap();
+        assert!(latency < 10_000);
+
+        let output = runtime.block_on(tool.call(args(false))).unwrap();
+        let json: Value = serde_json::from_str(&output).unwrap();
+        assert!(json.get("search_latency_ms").is_none());
+    }
+
+    #[test]
+    fn test_search_structured() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![
+                    mock_item("Iberia", "IB6", 540.0),
+                    mock_item("Vueling", "VY1", 410.0),
+                ]),
+                "USD",
+            )
+            .options,
+            ..Default::default()
+        };
+        let calls = Arc::clone(&provider.calls);
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = || FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            adults: Some(2),
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let result = runtime.block_on(tool.search_structured(args())).unwrap();
+        assert_eq!(result.options[0].flight_number, "VY1");
+        let query = &result.query_echo;
+        assert_eq!(query.resolved_source.sky_id, "FAKE-SRC");
+        assert_eq!(query.trip_type, TripType::OneWay);
+        assert_eq!(
+            (query.departure_date.as_str(), query.adults),
+            ("2025-06-01", 2)
+        );
+        assert_eq!(query.searched_on.to_string(), "2025-05-01");
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["query_echo"]["trip_type"], json!("one_way"));
+        assert_eq!(json["query_echo"]["currency"], json!("USD"));
+
+        // The tool's markdown is rendered from the same struct
+        let output = runtime.block_on(tool.call(args())).unwrap();
+        assert_eq!(output, result.to_markdown(true));
+
+        // Searches failing before any provider request still count as failed
+        calls.store(0, Ordering::SeqCst);
+        let failed_before = run_summary().errors;
+        let invalid = FlightSearchArgs {
+            sort_by: Some("legroom".to_string()),
+            ..args()
+        };
+        assert!(runtime.block_on(tool.search_structured(invalid)).is_err());
+        assert!(run_summary().errors > failed_before);
+
+        // json callers get the failure as an object carrying its code
+        let invalid = FlightSearchArgs {
+            sort_by: Some("legroom".to_string()),
+            response_format: Some("json".to_string()),
+            ..args()
+        };
+        let output = runtime.block_on(tool.call(invalid)).unwrap();
+        let json: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(json["error"]["code"], json!("ERR_INVALID_ARGUMENT"));
+        assert!(
+            json["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("Unsupported sort_by value: legroom")
+        );
+        // An unknown sort_by is rejected before resolving either place
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_json_output_coordinates() {
+        #[derive(Debug)]
+        struct GeoProvider;
+
+        impl FlightProvider for GeoProvider {
+            fn name(&self) -> &'static str {
+                "geo"
+            }
+
+            fn capabilities(&self) -> crate::provider::ProviderCapabilities {
+                Default::default()
+            }
+
+            fn resolve_location<'a>(
+                &'a self,
+                query: &'a str,
+                _budget: &'a RequestBudget,
+            ) -> ProviderFuture<'a, Location> {
+                Box::pin(async move {
+                    let mut suggestion = json!({ "navigation": { "relevantFlightParams": {
+                        "skyId": query, "entityId": format!("{}-id", query),
+                        "flightPlaceType": "AIRPORT"
+                    } } });
+                    if query == "BCN" {
+                        suggestion["presentation"] =
+                            json!({ "coordinates": { "latitude": 41.2974, "longitude": 2.0833 } });
+                    }
+                    crate::provider::parse_location(
+                        &json!({ "inputSuggest": [suggestion] }),
+                        query,
+                        false,
+                    )
+                })
+            }
+
+            fn search<'a>(
+                &'a self,
+                _params: &'a SearchParams,
+                _budget: &'a RequestBudget,
+            ) -> ProviderFuture<'a, ProviderResults> {
+                Box::pin(async move {
+                    Ok(parsed(
+                        &mock_response(vec![mock_item("Iberia", "IB6", 540.0)]),
+                        "USD",
+                    ))
+                })
+            }
+        }
+
+        let tool = FlightSearchTool::with_provider(Box::new(GeoProvider), Config::default());
+        let args = FlightSearchArgs {
+            source: "BCN".to_string(),
+            destination: "JFK".to_string(),
+            response_format: Some("json".to_string()),
+            ..Default::default()
+        };
+        let output = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args))
+            .unwrap();
+        let json: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(
+            json["origin_coordinates"],
+            json!({ "latitude": 41.2974, "longitude": 2.0833 })
+        );
+        // JFK's suggestion carries no coordinates, so the field is left out
+        assert!(json.get("destination_coordinates").is_none());
+    }
+
+    #[test]
+    fn test_invalid_departure_date() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![mock_item("Iberia", "IB6", 540.0)]),
+                "USD",
+            )
+            .options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let error = |departure_date: &str| {
+            let args = FlightSearchArgs {
+                source: "fake-src".to_string(),
+                destination: "fake-dst".to_string(),
+                departure_date: Some(departure_date.to_string()),
+                ..Default::default()
+            };
+            runtime.block_on(tool.call(args)).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            error("next tuesday"),
+            "Invalid argument: departure_date must be in YYYY-MM-DD format, got 'next tuesday'"
+        );
+        assert_eq!(
+            error(""),
+            "Invalid argument: departure_date must be in YYYY-MM-DD format, got ''"
+        );
+        assert_eq!(
+            error("2025-04-30"),
+            "Invalid argument: departure_date 2025-04-30 is in the past; today is 2025-05-01"
+        );
+        // Today itself is still bookable
+        let (departure, _) = travel_dates(
+            Some("2025-05-01".to_string()),
+            None,
+            TripType::OneWay,
+            &test_clock(),
+        )
+        .unwrap();
+        assert_eq!(departure, "2025-05-01");
+    }
+
+    #[test]
+    fn test_same_source_and_destination() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![mock_item("Iberia", "IB6", 540.0)]),
+                "USD",
+            )
+            .options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default());
+        let args = FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-src".to_string(),
+            ..Default::default()
+        };
+        let err = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid argument: source and destination are the same"
+        );
+    }
+
+    #[test]
+    fn test_call_rejects_return_before_departure() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![mock_item("Iberia", "IB6", 540.0)]),
+                "USD",
+            )
+            .options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let args = |return_date: &str| FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-10".to_string()),
+            return_date: Some(return_date.to_string()),
+            ..Default::default()
+        };
+
+        let err = runtime.block_on(tool.call(args("2025-06-03"))).unwrap_err();
+        assert_eq!(err.code(), "ERR_INVALID_ARGUMENT");
+        assert_eq!(
+            err.to_string(),
+            "Invalid argument: return_date 2025-06-03 is before departure_date 2025-06-10"
+        );
+        // A same-day return is a valid roundtrip
+        let output = runtime.block_on(tool.call(args("2025-06-10"))).unwrap();
+        assert!(output.contains("**Airline**: Iberia"));
+    }
+
+    #[test]
+    fn test_travel_dates_with_return_only() {
+        let clock = FixedClock(Utc.with_ymd_and_hms(2025, 5, 1, 12, 0, 0).unwrap());
+        let dates = |departure: Option<&str>, return_date: Option<&str>| {
+            travel_dates(
+                departure.map(String::from),
+                return_date.map(String::from),
+                TripType::RoundTrip,
+                &clock,
+            )
+            .map(|(departure, return_date)| (departure, return_date.unwrap()))
+        };
+
+        // Return before the default departure pulls departure back a week
+        assert_eq!(
+            dates(None, Some("2025-05-20")).unwrap(),
+            ("2025-05-13".to_string(), "2025-05-20".to_string())
+        );
+        // ...but never before today
+        assert_eq!(
+            dates(None, Some("2025-05-03")).unwrap(),
+            ("2025-05-01".to_string(), "2025-05-03".to_string())
+        );
+        // A far-off return keeps the usual default
+        assert_eq!(
+            dates(None, Some("2025-07-15")).unwrap(),
+            ("2025-05-31".to_string(), "2025-07-15".to_string())
+        );
+        assert_eq!(
+            dates(None, None).unwrap(),
+            ("2025-05-31".to_string(), "2025-06-07".to_string())
+        );
+        assert_eq!(
+            dates(None, Some("2025-04-20")).unwrap_err().to_string(),
+            "Invalid argument: return_date 2025-04-20 is in the past; provide a departure_date"
+        );
+        assert_eq!(
+            dates(Some("2025-06-10"), Some("2025-06-01"))
+                .unwrap_err()
+                .to_string(),
+            "Invalid argument: return_date 2025-06-01 is before departure_date 2025-06-10"
+        );
+    }
+
+    #[test]
+    fn test_quality_gate() {
+        let broken = json!({ "legs": [{ "segments": [] }], "pricingOptions": [] });
+        let data = mock_response(vec![
+            broken.clone(),
+            broken.clone(),
+            broken,
+            mock_item("Iberia", "IB6", 540.0),
+        ]);
+        let err = check_result_quality(&parsed(&data, "USD").options, Some(0.5)).unwrap_err();
+        assert_eq!(err.code(), "ERR_SCHEMA_MISMATCH");
+        assert_eq!(
+            err.to_string(),
+            "Response schema mismatch: 3 of 4 itineraries are missing an airline or price"
+        );
+        // Disabled gate and tolerant thresholds let the response through
+        assert!(check_result_quality(&parsed(&data, "USD").options, None).is_ok());
+        assert!(check_result_quality(&parsed(&data, "USD").options, Some(0.8)).is_ok());
+        assert!(
+            check_result_quality(&parsed(&mock_response(vec![]), "USD").options, Some(0.0)).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_trip_dates_weekdays() {
+        assert_eq!(
+            trip_dates_line("2024-06-01", Some("2024-06-08"), &ENGLISH),
+            "Departing Sat, Jun 1 · Returning Sat, Jun 8"
+        );
+        assert_eq!(
+            trip_dates_line("2025-06-02", Some("2025-06-10"), &SPANISH),
+            "Salida lun, jun 2 · Regreso mar, jun 10"
+        );
+    }
+
+    #[test]
+    fn test_resolve_passengers() {
+        let skyscanner = SkyscannerProvider::from_config(&Config::default()).capabilities();
+        let party = |adults, children, infants| FlightSearchArgs {
+            adults,
+            children,
+            infants,
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_passengers(&party(None, None, None), &skyscanner).unwrap(),
+            Passengers {
+                adults: 1,
+                children: 0,
+                infants: 0
+            }
+        );
+        assert_eq!(
+            resolve_passengers(&party(Some(2), Some(3), Some(2)), &skyscanner).unwrap(),
+            Passengers {
+                adults: 2,
+                children: 3,
+                infants: 2
+            }
+        );
+        // Without a provider limit only the shared rules apply
+        let unlimited = ProviderCapabilities::default();
+        assert!(resolve_passengers(&party(Some(12), None, None), &unlimited).is_ok());
+
+        let err = resolve_passengers(&party(Some(0), Some(2), None), &skyscanner).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid argument: at least one adult must travel"
+        );
+        let err = resolve_passengers(&party(None, None, Some(2)), &skyscanner).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid argument: 2 infants need at least as many adults, got 1"
+        );
+        let err = resolve_passengers(&party(Some(12), None, None), &skyscanner).unwrap_err();
+        assert_eq!(err.code(), "ERR_PARTY_TOO_LARGE");
+        assert_eq!(
+            err.to_string(),
+            "Party of 12 exceeds the provider limit of 9 passengers per booking; search for smaller groups separately"
+        );
+        assert!(resolve_passengers(&party(Some(9), None, None), &skyscanner).is_ok());
+        // Children and infants count towards the limit
+        let err = resolve_passengers(&party(Some(4), Some(4), Some(2)), &skyscanner).unwrap_err();
+        assert!(err.to_string().starts_with("Party of 10 exceeds"));
+    }
+
+    #[test]
+    fn test_option_signature() {
+        let parse = |item: &Value| parse_flight_option(&typed(item), "USD");
+        let item = mock_item("Iberia", "IB6", 540.0);
+        assert_eq!(parse(&item).signature(), parse(&item.clone()).signature());
+
+        let mut cheaper = item.clone();
+        cheaper["pricingOptions"][0]["price"]["amount"] = json!(539.99);
+        let mut later = item.clone();
+        later["legs"][0]["departure"] = json!("2025-06-01T11:00:00");
+        let other_flight = mock_item("Iberia", "IB8", 540.0);
+        let return_leg = |flight_number: &str| {
+            json!({
+                "segments": [{ "flightNumber": flight_number }],
+                "departure": "2025-06-08T12:00:00",
+                "arrival": "2025-06-08T15:00:00"
+            })
+        };
+        let mut round_trip = item.clone();
+        round_trip["legs"]
+            .as_array_mut()
+            .unwrap()
+            .push(return_leg("IB7"));
+        let mut other_return = item.clone();
+        other_return["legs"]
+            .as_array_mut()
+            .unwrap()
+            .push(return_leg("IB9"));
+        assert_ne!(
+            parse(&round_trip).signature(),
+            parse(&other_return).signature()
+        );
+        for differing in [cheaper, later, other_flight, round_trip] {
+            assert_ne!(parse(&item).signature(), parse(&differing).signature());
+        }
+    }
+
+    #[test]
+    fn test_ndjson_output() {
+        let data = mock_response(vec![
+            mock_item("Delta", "DL1", 480.0),
+            mock_item("Iberia", "IB6", 540.0),
+            mock_item("United", "UA1", 600.0),
+        ]);
+        let options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        let output = to_ndjson(&options).unwrap();
+
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), options.len());
+        for (line, option) in lines.iter().zip(&options) {
+            let parsed: Value = serde_json::from_str(line).unwrap();
+            assert_eq!(parsed["flight_number"], json!(option.flight_number));
+        }
+        assert_eq!(to_ndjson(&[]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_min_connection_time() {
+        let mut connecting = mock_item("Delta", "DL1", 480.0);
+        connecting["legs"][0]["minConnectionTime"] = json!(75);
+        let mut nonstop = mock_item("Iberia", "IB6", 540.0);
+        nonstop["legs"][0]["stopCount"] = json!(0);
+        nonstop["legs"][0]["minConnectionTime"] = json!(45);
+        let data = mock_response(vec![connecting, nonstop]);
+
+        let options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        assert_eq!(options[0].min_connection_minutes, Some(75));
+        let output = format_flight_options(&options, &ENGLISH);
+        assert!(output.contains("**Min Connection**: 75 min"));
+        // Non-stop options have no connection to explain
+        assert!(!output.contains("45 min"));
+    }
+
+    #[test]
+    fn test_group_by_stops() {
+        let with_stops = |number: &str, price: f64, stops: u64| {
+            let mut item = mock_item("Delta", number, price);
+            item["legs"][0]["stopCount"] = json!(stops);
+            item
+        };
+        let data = mock_response(vec![
+            with_stops("DL1", 620.0, 1),
+            with_stops("DL2", 900.0, 0),
+            with_stops("DL3", 410.0, 3),
+            with_stops("DL4", 480.0, 1),
+            with_stops("DL5", 390.0, 2),
+            with_stops("DL6", 850.0, 0),
+        ]);
+        let options = parse_flight_options(
+            parsed(&data, "USD").options,
+            &OptionFilters::default(),
+            usize::MAX,
+        );
+        let grouped = group_options_by_stops(options, 5);
+
+        let order: Vec<_> = grouped.iter().map(|o| o.flight_number.as_str()).collect();
+        assert_eq!(order, vec!["DL6", "DL2", "DL4", "DL1", "DL5", "DL3"]);
+        let output = format_grouped_by_stops(&grouped, &ENGLISH);
+        let sections: Vec<_> = output.lines().filter(|l| l.starts_with("### ")).collect();
+        assert_eq!(sections, vec!["### Non-stop", "### 1 stop", "### 2+ stops"]);
+        assert!(output.contains("6. **Airline**: Delta"));
+    }
+
+    #[test]
+    fn test_leg_airports() {
+        // Searched LON, but the itinerary flies Gatwick to Newark
+        let mut item = mock_item("United", "UA15", 610.0);
+        item["legs"][0]["origin"] = json!({ "id": "LGW", "displayCode": "LGW" });
+        item["legs"][0]["destination"] = json!({ "id": "EWR", "displayCode": "EWR" });
+        let option = parse_flight_option(&typed(&item), "USD");
+        assert_eq!(option.origin_airport, "LGW");
+        assert_eq!(option.destination_airport, "EWR");
+
+        let output = format_flight_options(&[option], &ENGLISH);
+        assert!(output.contains(" (LGW)\n"));
+        assert!(output.contains(" (EWR)\n"));
+    }
+
+    #[test]
+    fn test_one_way_dates() {
+        let clock = FixedClock(Utc.with_ymd_and_hms(2025, 5, 1, 12, 0, 0).unwrap());
+        let one_way = |departure: Option<&str>, return_date: Option<&str>| {
+            travel_dates(
+                departure.map(String::from),
+                return_date.map(String::from),
+                TripType::OneWay,
+                &clock,
+            )
+        };
+        // No return is fabricated for a one-way trip
+        assert_eq!(
+            one_way(None, None).unwrap(),
+            ("2025-05-31".to_string(), None)
+        );
+        assert_eq!(
+            one_way(Some("2025-06-10"), None).unwrap(),
+            ("2025-06-10".to_string(), None)
+        );
+        assert!(one_way(None, Some("2025-06-10")).is_err());
+        assert_eq!(
+            trip_dates_line("2024-06-01", None, &ENGLISH),
+            "Departing Sat, Jun 1"
+        );
+    }
+
+    #[test]
+    fn test_resolution_error_names_the_input() {
+        let provider = BareProvider {
+            options: parsed(&mock_response(vec![]), "USD").options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-nowhere".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            ..Default::default()
+        };
+        let err = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(tool.call(args))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid response: Resolving destination 'fake-nowhere': No valid airport found in auto-complete response"
+        );
+    }
+
+    #[test]
+    fn test_result_limit() {
+        assert_eq!(result_limit(None, 5), 5);
+        assert_eq!(result_limit(Some(3), 5), 3);
+        assert_eq!(result_limit(Some(50), 5), 50);
+        // Out of range requests are clamped rather than rejected
+        assert_eq!(result_limit(Some(0), 5), 1);
+        assert_eq!(result_limit(Some(-4), 5), 1);
+        assert_eq!(result_limit(Some(51), 5), 50);
+        assert_eq!(result_limit(Some(1000), 5), 50);
+    }
+
+    #[test]
+    fn test_call_respects_max_results() {
+        let items = (1..=8)
+            .map(|i| mock_item("Delta", &format!("DL{}", i), 400.0 + i as f64))
+            .collect();
+        let provider = BareProvider {
+            options: parsed(&mock_response(items), "USD").options,
+            ..Default::default()
+        };
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = |max_results| FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            max_results,
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let count = |output: &str| output.matches("**Airline**").count();
+
+        let output = runtime.block_on(tool.call(args(Some(3)))).unwrap();
+        assert_eq!(count(&output), 3);
+        // The configured default applies when the model doesn't ask
+        let output = runtime.block_on(tool.call(args(None))).unwrap();
+        assert_eq!(count(&output), Config::default().default_max_results);
+        let output = runtime.block_on(tool.call(args(Some(500)))).unwrap();
+        assert_eq!(count(&output), 8);
+    }
+
+    #[test]
+    fn test_location_cache_skips_repeat_lookups() {
+        #[derive(Debug, Default)]
+        struct CountingProvider {
+            lookups: AtomicUsize,
+        }
+
+        impl FlightProvider for CountingProvider {
+            fn name(&self) -> &'static str {
+                "counting"
+            }
+
+            fn capabilities(&self) -> crate::provider::ProviderCapabilities {
+                Default::default()
+            }
+
+            fn resolve_location<'a>(
+                &'a self,
+                _query: &'a str,
+                _budget: &'a RequestBudget,
+            ) -> ProviderFuture<'a, Location> {
+                self.lookups.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move {
+                    Ok(Location {
+                        sky_id: "BCN".to_string(),
+                        entity_id: "95565085".to_string(),
+                        metro_name: None,
+                        coordinates: None,
+                    })
+                })
+            }
+
+            fn search<'a>(
+                &'a self,
+                _params: &'a SearchParams,
+                _budget: &'a RequestBudget,
+            ) -> ProviderFuture<'a, ProviderResults> {
+                Box::pin(async move { Ok(ProviderResults::default()) })
+            }
+        }
+
+        let provider = CountingProvider::default();
+        let budget = RequestBudget::new(10);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let cache = location_cache(&Config::default());
+        for query in ["Barcelona-cache-test", " barcelona-CACHE-test "] {
+            let location = runtime
+                .block_on(resolve_location(&provider, query, &budget, &cache))
+                .unwrap();
+            assert_eq!(location.sky_id, "BCN");
+        }
+        // The normalized query hits the cache, so only one lookup reaches the provider
+        assert_eq!(provider.lookups.load(Ordering::SeqCst), 1);
+
+        // The injected config sizes the cache, so a second place evicts the first
+        let cache = location_cache(&Config {
+            location_cache_size: 1,
+            ..Config::default()
+        });
+        for query in ["Barcelona", "Madrid", "Barcelona"] {
+            runtime
+                .block_on(resolve_location(&provider, query, &budget, &cache))
+                .unwrap();
+        }
+        assert_eq!(provider.lookups.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_book_by_date() {
+        let today = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+        let book_by = |departure: &str| {
+            let departure = NaiveDate::parse_from_str(departure, DATE_FORMAT).unwrap();
+            book_by_date(departure, today, 60).map(|date| book_by_line(date, today, &ENGLISH))
+        };
+        // Far-off departures leave time to watch fares
+        assert_eq!(
+            book_by("2025-10-01").as_deref(),
+            Some("Consider booking by 2025-08-02")
+        );
+        assert_eq!(
+            book_by("2025-07-15").as_deref(),
+            Some("Consider booking by 2025-05-16")
+        );
+        // Inside the lead time the deadline has passed
+        assert_eq!(
+            book_by("2025-05-20").as_deref(),
+            Some("Consider booking now")
+        );
+        assert_eq!(
+            book_by("2025-05-01").as_deref(),
+            Some("Consider booking now")
+        );
+        assert_eq!(book_by("2025-04-30"), None);
+        let date = NaiveDate::from_ymd_opt(2025, 8, 2).unwrap();
+        assert_eq!(
+            book_by_line(date, today, &SPANISH),
+            "Conviene reservar antes del 2025-08-02"
+        );
+        assert_eq!(book_by_line(today, today, &SPANISH), "Conviene reservar ya");
+    }
+
+    #[test]
+    fn test_strict_cabin_drops_mixed_itineraries() {
+        let mut mixed = mock_item("Lufthansa", "LH401", 3100.0);
+        mixed["legs"][0]["segments"] = json!([
+            { "flightNumber": "LH401", "cabinClass": "BUSINESS" },
+            { "flightNumber": "LH1130", "cabinClass": "ECONOMY" }
+        ]);
+        let mut business = mock_item("Lufthansa", "LH403", 3400.0);
+        business["legs"][0]["segments"] = json!([
+            { "flightNumber": "LH403", "cabinClass": "BUSINESS" },
+            { "flightNumber": "LH1132", "cabinClass": "BUSINESS" }
+        ]);
+        // No per-segment cabins reported, so it can't be ruled out
+        let unknown = mock_item("Lufthansa", "LH405", 3300.0);
+        let data = mock_response(vec![mixed, business, unknown]);
+
+        let filters = OptionFilters {
+            strict_cabin: Some("business".to_string()),
+            ..Default::default()
+        };
+        let options = parse_flight_options(parsed(&data, "USD").options, &filters, usize::MAX);
+        let kept: Vec<_> = options.iter().map(|o| o.flight_number.as_str()).collect();
+        assert_eq!(kept, vec!["LH403", "LH405"]);
+
+        let options = parse_flight_options(
+            parsed(&data, "USD").options,
+            &OptionFilters::default(),
+            usize::MAX,
+        );
+        assert_eq!(options.len(), 3);
+    }
+
+    #[test]
+    fn test_provider_total_results() {
+        let mut data = mock_response(vec![
+            mock_item("Iberia", "IB6", 540.0),
+            mock_item("Delta", "DL1", 610.0),
+        ]);
+        data["itineraries"]["totalResults"] = json!(214);
+        let result = parse_search_result(parsed(&data, "USD"), &OptionFilters::default(), 1);
+        assert_eq!(result.total_results, Some(214));
+        assert_eq!(
+            serde_json::to_value(&result).unwrap()["total_results"],
+            json!(214)
+        );
+        assert_eq!(
+            results_count_line(214, result.options.len(), &ENGLISH),
+            "Found 214 itineraries, showing 1"
+        );
+
+        // Wrapped responses report it under context
+        let wrapped = json!({ "data": { "context": { "totalResults": 3 } } });
+        assert_eq!(parsed(&wrapped, "USD").total_results, Some(3));
+        let result = parse_search_result(
+            parsed(&mock_response(vec![]), "USD"),
+            &OptionFilters::default(),
+            5,
+        );
+        assert_eq!(result.total_results, None);
+        assert!(
+            serde_json::to_value(&result)
+                .unwrap()
+                .get("total_results")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_price_per_km() {
+        let mut option = parse_flight_option(&typed(&mock_item("Iberia", "IB6", 540.0)), "USD");
+        assert_eq!(option.price_per_km, None);
+        assert!(!format_flight_options(&[option], &ENGLISH).contains("Price per km"));
+
+        option = parse_flight_option(&typed(&mock_item("Iberia", "IB6", 540.0)), "USD");
+        option.price_per_km = price_per_km(&option, 6000.0);
+        assert_eq!(option.price_per_km, Some(0.09));
+        assert!(
+            format_flight_options(&[option.clone()], &ENGLISH)
+                .contains("**Price per km**: 0.090 USD")
+        );
+        assert_eq!(price_per_km(&option, 0.0), None);
+
+        // A round-trip fare covers the distance both ways
+        let mut roundtrip = mock_item("Iberia", "IB6", 540.0);
+        roundtrip["legs"].as_array_mut().unwrap().push(json!({
+            "segments": [{ "flightNumber": "IB7" }]
+        }));
+        let option = parse_flight_option(&typed(&roundtrip), "USD");
+        assert_eq!(price_per_km(&option, 6000.0), Some(0.045));
+    }
+
+    #[test]
+    fn test_return_leg() {
+        let mut roundtrip = mock_item("Iberia", "IB6", 540.0);
+        roundtrip["legs"].as_array_mut().unwrap().push(json!({
+            "carriers": { "marketing": [{ "name": "Iberia" }] },
+            "segments": [{ "flightNumber": "IB7" }],
+            "departure": "2025-06-08T12:00:00",
+            "arrival": "2025-06-08T17:45:00",
+            "durationInMinutes": 705,
+            "stopCount": 0
+        }));
+        let option = parse_flight_option(&typed(&roundtrip), "USD");
+        let leg = option.return_leg.as_ref().unwrap();
+        assert_eq!(leg.airline, "Iberia");
+        assert_eq!(leg.flight_numbers, vec!["IB7"]);
+        assert_eq!(leg.departure, "2025-06-08T12:00:00");
+        assert_eq!(leg.duration, "11 hours 45 minutes");
+        assert_eq!(leg.stops, 0);
+
+        let output = format_flight_options(&[option], &ENGLISH);
+        assert!(output.contains(
+            "   - **Return**:\n     - **Airline**: Iberia\n     - **Flight Numbers**: IB7\n     - **Departure**: 2025-06-08T12:00:00\n"
+        ));
+        assert!(output.contains("     - **Stops**: Non-stop\n"));
+
+        // One-way itineraries have a single leg and no return section
+        let option = parse_flight_option(&typed(&mock_item("Iberia", "IB6", 540.0)), "USD");
+        assert_eq!(option.return_leg, None);
+        assert!(!format_flight_options(&[option], &ENGLISH).contains("Return"));
+    }
+
+    #[test]
+    fn test_round_trip_price_per_hour() {
+        let mut roundtrip = mock_item("Iberia", "IB6", 900.0);
+        roundtrip["legs"][0]["durationInMinutes"] = json!(480);
+        roundtrip["legs"].as_array_mut().unwrap().push(json!({
+            "segments": [{ "flightNumber": "IB7" }],
+            "durationInMinutes": 420
+        }));
+        // 900 over 15 hours in the air, not over the 8 hour outbound alone
+        let option = parse_flight_option(&typed(&roundtrip), "USD");
+        assert_eq!(option.price_per_hour, Some(60.0));
+
+        // An unknown leg duration leaves the rate unknown
+        roundtrip["legs"][1]
+            .as_object_mut()
+            .unwrap()
+            .remove("durationInMinutes");
+        let option = parse_flight_option(&typed(&roundtrip), "USD");
+        assert_eq!(option.price_per_hour, None);
+    }
+
+    #[test]
+    fn test_per_leg_cabins() {
+        let mut roundtrip = mock_item("Iberia", "IB6", 1840.0);
+        roundtrip["legs"][0]["segments"][0]["cabinClass"] = json!("ECONOMY");
+        roundtrip["legs"].as_array_mut().unwrap().push(json!({
+            "carriers": { "marketing": [{ "name": "Iberia" }] },
+            "segments": [
+                { "flightNumber": "IB7", "cabinClass": "business" },
+                { "flightNumber": "IB3166", "cabinClass": "business" }
+            ],
+            "departure": "2025-06-08T12:00:00",
+            "arrival": "2025-06-08T19:10:00",
+            "durationInMinutes": 790,
+            "stopCount": 1
+        }));
+        let option = parse_flight_option(&typed(&roundtrip), "USD");
+        assert_eq!(option.cabins, vec!["economy"]);
+        assert_eq!(option.return_leg.as_ref().unwrap().cabins, vec!["business"]);
+
+        let output = format_flight_options(&[option], &ENGLISH);
+        assert!(output.contains("   - **Cabin**: economy\n"));
+        assert!(output.contains("     - **Cabin**: business\n"));
+
+        // Nothing is shown when the provider doesn't report cabins
+        let option = parse_flight_option(&typed(&mock_item("Iberia", "IB6", 540.0)), "USD");
+        assert!(option.cabins.is_empty());
+        assert!(!format_flight_options(&[option], &ENGLISH).contains("**Cabin**"));
+    }
+
+    #[test]
+    fn test_filter_drop_counts() {
+        let mut self_transfer = mock_item("Ryanair", "FR1", 300.0);
+        self_transfer["isSelfTransfer"] = json!(true);
+        let mut nonstop = mock_item("Iberia", "IB6", 480.0);
+        nonstop["legs"][0]["stopCount"] = json!(0);
+        let mut expensive = mock_item("Delta", "DL2", 900.0);
+        expensive["legs"][0]["stopCount"] = json!(0);
+        let data = mock_response(vec![
+            mock_item("Delta", "DL1", 620.0),
+            nonstop,
+            self_transfer,
+            expensive,
+            mock_item("United", "UA1", 700.0),
+        ]);
+        let filters = OptionFilters {
+            exclude_self_transfer: true,
+            max_stops: Some(0),
+            max_price: Some((650.0, "USD".to_string())),
+            ..Default::default()
+        };
+        let (options, drops) = filter_flight_options(parsed(&data, "USD").options, &filters, 5);
+        assert_eq!(options.len(), 1);
+        // Each option counts against the first filter that drops it
+        assert_eq!(
+            drops,
+            FilterDrops {
+                self_transfer: 1,
+                stops: 2,
+                price: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_rank_reasons() {
+        let mut slow = mock_item("Delta", "DL1", 450.0);
+        slow["legs"][0]["durationInMinutes"] = json!(840);
+        let mut euro = mock_item("Vueling", "VY1", 300.0);
+        euro["pricingOptions"][0]["price"]["currencyCode"] = json!("EUR");
+        let data = mock_response(vec![mock_item("Iberia", "IB6", 480.0), slow, euro]);
+        let mut options =
+            parse_flight_options(parsed(&data, "USD").options, &OptionFilters::default(), 5);
+        sort_options(&mut options, SortBy::Price);
+        group_by_currency(&mut options, "USD");
+
+        assert_eq!(
+            rank_reasons(&options, SortBy::Price, "USD"),
+            vec![
+                "lowest price",
+                "30.00 USD more than the cheapest",
+                "quoted in EUR, listed after fares in USD",
+            ]
+        );
+        sort_options(&mut options, SortBy::Duration);
+        group_by_currency(&mut options, "USD");
+        assert_eq!(
+            rank_reasons(&options, SortBy::Duration, "USD")[..2],
+            [
+                "shortest flight time",
+                "1 hours 30 minutes longer than the fastest"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_agent_rating_filter_and_preference() {
+        let rated = |airline: &str, flight_number: &str, price: f64, rating: Option<f64>| {
+            let mut item = mock_item(airline, flight_number, price);
+            item["pricingOptions"][0]["items"] = json!([{ "agentId": "agent", "rating": rating }]);
+            item
+        };
+        let data = mock_response(vec![
+            rated("Iberia", "IB6", 480.0, Some(3.1)),
+            rated("Vueling", "VY1", 480.0, Some(4.7)),
+            rated("Delta", "DL1", 520.0, None),
+            rated("United", "UA1", 450.0, Some(2.2)),
+        ]);
+        let filters = OptionFilters {
+            min_agent_rating: Some(3.0),
+            ..Default::default()
+        };
+        let mut options = parse_flight_options(parsed(&data, "USD").options, &filters, 5);
+        assert_eq!(options[0].agent_rating, Some(3.1));
+        // UA1's agent is rated below the minimum; DL1's is unrated and kept
+        let order = |options: &[FlightOption]| {
+            options
+                .iter()
+                .map(|o| o.flight_number.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(&options), vec!["IB6", "VY1", "DL1"]);
+
+        sort_options(&mut options, SortBy::Price);
+        assert_eq!(order(&options), vec!["IB6", "VY1", "DL1"]);
+        prefer_rated_agents(&mut options);
+        sort_options(&mut options, SortBy::Price);
+        assert_eq!(order(&options), vec!["VY1", "IB6", "DL1"]);
+    }
+
+    #[test]
+    fn test_max_price_filter() {
+        let mut euro = mock_item("Vueling", "VY1", 900.0);
+        euro["pricingOptions"][0]["price"]["currencyCode"] = json!("EUR");
+        let data = mock_response(vec![
+            mock_item("Delta", "DL1", 620.0),
+            mock_item("Iberia", "IB6", 480.0),
+            euro,
+            mock_item("United", "UA1", 500.0),
+        ]);
+        let filters = OptionFilters {
+            max_price: Some((500.0, "USD".to_string())),
+            ..Default::default()
+        };
+        let options = parse_flight_options(parsed(&data, "USD").options, &filters, 5);
+        // The cap is inclusive, and fares in other currencies aren't compared
+        let kept: Vec<_> = options.iter().map(|o| o.flight_number.as_str()).collect();
+        assert_eq!(kept, vec!["IB6", "VY1", "UA1"]);
+    }
+
+    #[test]
+    fn test_max_price_empty_message() {
+        let provider = BareProvider {
+            options: parsed(
+                &mock_response(vec![mock_item("Delta", "DL1", 620.0)]),
+                "USD",
+            )
+            .options,
+            ..Default::default()
+        };
+        let calls = Arc::clone(&provider.calls);
+        let tool = FlightSearchTool::with_provider(Box::new(provider), Config::default())
+            .with_clock(Box::new(test_clock()));
+        let args = |max_price| FlightSearchArgs {
+            source: "fake-src".to_string(),
+            destination: "fake-dst".to_string(),
+            departure_date: Some("2025-06-01".to_string()),
+            max_price: Some(max_price),
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let output = runtime.block_on(tool.call(args(400.0))).unwrap();
+        assert_eq!(output, "No flights found under 400 USD");
+        // A bad max_price is rejected before any provider request
+        calls.store(0, Ordering::SeqCst);
+        let err = runtime.block_on(tool.call(args(-1.0))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid argument: max_price must be a positive amount, got -1"
+        );
+        assert!(runtime.block_on(tool.call(args(f64::NAN))).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_nonstop_only() {
+        let text = include_str!("../tests/fixtures/roundtrip_list_mixed_stops.json");
+        let response: ListResponse = serde_json::from_str(text).unwrap();
+        let filters = OptionFilters {
+            nonstop_only: true,
+            ..Default::default()
+        };
+        let options =
+            parse_flight_options(skyscanner_results(&response, "USD").options, &filters, 5);
+        let kept: Vec<_> = options.iter().map(|o| o.flight_number.as_str()).collect();
+        assert_eq!(kept, vec!["IB6658", "AA140"]);
+        // Connecting options don't use up the result limit
+        let options =
+            parse_flight_options(skyscanner_results(&response, "USD").options, &filters, 2);
+        assert_eq!(options.len(), 2);
+        assert!(options.iter().all(|o| o.stops == 0));
+
+        let options = parse_flight_options(
+            skyscanner_results(&response, "USD").options,
+            &OptionFilters::default(),
+            5,
+        );
+        assert_eq!(options.len(), 4);
+    }
+
+    #[test]
+    fn test_max_stops() {
+        let with_stops = |number: &str, stops: u64| {
+            let mut item = mock_item("Delta", number, 400.0);
+            item["legs"][0]["stopCount"] = json!(stops);
+            item
+        };
+        let data = typed(&mock_response(vec![
+            with_stops("DL0", 0),
+            with_stops("DL1", 1),
+            with_stops("DL2", 2),
+        ]));
+        let kept = |filters: &OptionFilters| {
+            parse_flight_options(skyscanner_results(&data, "USD").options, filters, 5)
+                .into_iter()
+                .map(|o| o.flight_number)
+                .collect::<Vec<_>>()
+        };
+
+        let mut filters = OptionFilters {
+            max_stops: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(kept(&filters), vec!["DL0", "DL1"]);
+        // nonstop_only is stricter than a one stop cap
+        filters.nonstop_only = true;
+        assert_eq!(kept(&filters), vec!["DL0"]);
+        filters.max_stops = Some(0);
+        filters.nonstop_only = false;
+        assert_eq!(kept(&filters), vec!["DL0"]);
+    }
+
+    #[test]
+    fn test_refundable_only() {
+        let with_refund = |number: &str, refundable: Option<bool>| {
+            let mut item = mock_item("Iberia", number, 540.0);
+            if let Some(refundable) = refundable {
+                item["pricingOptions"][0]["isRefundable"] = json!(refundable);
+            }
+            item
+        };
+        let data = typed(&mock_response(vec![
+            with_refund("IB1", Some(false)),
+            with_refund("IB2", Some(true)),
+            with_refund("IB3", None),
+        ]));
+        let kept = |filters: &OptionFilters| {
+            parse_flight_options(skyscanner_results(&data, "USD").options, filters, 5)
+                .into_iter()
+                .map(|o| o.flight_number)
+                .collect::<Vec<_>>()
+        };
+
+        let mut filters = OptionFilters {
+            refundable_only: true,
+            keep_unknown_refundability: true,
+            ..Default::default()
+        };
+        assert_eq!(kept(&filters), vec!["IB2", "IB3"]);
+        filters.keep_unknown_refundability = false;
+        assert_eq!(kept(&filters), vec!["IB2"]);
+        assert_eq!(kept(&OptionFilters::default()).len(), 3);
+
+        let options = parse_flight_options(
+            skyscanner_results(&data, "USD").options,
+            &OptionFilters::default(),
+            5,
+        );
+        let output = format_flight_options(&options, &ENGLISH);
+        assert!(output.contains("**Refundable**: No"));
+        assert!(output.contains("**Refundable**: Yes"));
+        assert_eq!(output.matches("**Refundable**").count(), 2);
+    }
 }
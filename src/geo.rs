@@ -0,0 +1,64 @@
+use serde::Serialize;
+
+/// Mean Earth radius used for great-circle distances
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Latitude and longitude in decimal degrees
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// Great-circle distance to `other` using the haversine formula
+    pub fn distance_km(&self, other: &Coordinates) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+/// Rounded distance for display, e.g. "≈6,200 km": nearest 100 km, or 10 km below 1,000
+pub fn format_distance(km: f64) -> String {
+    let step = if km < 1000.0 { 10.0 } else { 100.0 };
+    let rounded = ((km / step).round() * step) as u64;
+    let digits = rounded.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    format!("≈{} km", grouped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_between_known_airports() {
+        let jfk = Coordinates {
+            latitude: 40.6413,
+            longitude: -73.7781,
+        };
+        let lhr = Coordinates {
+            latitude: 51.47,
+            longitude: -0.4543,
+        };
+        assert!((jfk.distance_km(&lhr) - 5540.0).abs() < 5.0);
+        assert!((jfk.distance_km(&lhr) - lhr.distance_km(&jfk)).abs() < 1e-9);
+        assert_eq!(jfk.distance_km(&jfk), 0.0);
+    }
+
+    #[test]
+    fn test_format_distance() {
+        assert_eq!(format_distance(6183.4), "≈6,200 km");
+        assert_eq!(format_distance(12_345.0), "≈12,300 km");
+        assert_eq!(format_distance(344.0), "≈340 km");
+    }
+}
@@ -0,0 +1,276 @@
+/// Translatable strings used when rendering results for the model
+#[derive(Debug)]
+pub struct OutputLabels {
+    pub header: &'static str,
+    pub no_flights: &'static str,
+    pub no_flights_under: &'static str,
+    pub airline: &'static str,
+    pub flight_number: &'static str,
+    pub flight_numbers: &'static str,
+    pub marketed_as: &'static str,
+    pub operated_as: &'static str,
+    pub departure: &'static str,
+    pub arrival: &'static str,
+    pub duration: &'static str,
+    pub stops: &'static str,
+    pub non_stop: &'static str,
+    pub stop_count: &'static str,
+    pub one_stop: &'static str,
+    pub two_plus_stops: &'static str,
+    pub segments: &'static str,
+    pub cabin: &'static str,
+    pub price: &'static str,
+    pub price_per_hour: &'static str,
+    pub price_per_km: &'static str,
+    pub checked_bag: &'static str,
+    pub refundable: &'static str,
+    pub yes: &'static str,
+    pub no: &'static str,
+    pub quote_age: &'static str,
+    pub min_connection: &'static str,
+    pub also_priced: &'static str,
+    pub warning: &'static str,
+    pub self_transfer: &'static str,
+    pub currency_mismatch: &'static str,
+    pub price_confidence: &'static str,
+    pub preliminary_price: &'static str,
+    pub preliminary: &'static str,
+    pub found: &'static str,
+    pub itineraries_showing: &'static str,
+    pub cabin_fallback: &'static str,
+    pub metro_search: &'static str,
+    pub departing: &'static str,
+    pub returning: &'static str,
+    pub return_leg: &'static str,
+    pub distance: &'static str,
+    /// Abbreviated weekday names starting on Monday
+    pub weekdays: [&'static str; 7],
+    /// Abbreviated month names starting in January
+    pub months: [&'static str; 12],
+    pub all_airports: &'static str,
+    pub ranking: &'static str,
+    pub agent_rating: &'static str,
+    pub price_calendar: &'static str,
+    /// Marks a date or cabin without fares
+    pub no_fares: &'static str,
+    /// Plural stop count in the share summary, e.g. "2 stops"
+    pub stops_plural: &'static str,
+    pub share_cheapest: &'static str,
+    /// Joins the fare and the airline in the share summary
+    pub share_on: &'static str,
+    pub booking: &'static str,
+    pub days_out: &'static str,
+    /// Booking advice for each horizon in `LEAD_TIME_HORIZONS`, longest first
+    pub lead_time_advice: [&'static str; 4],
+    pub departure_passed: &'static str,
+    pub day_before: &'static str,
+    pub selected: &'static str,
+    pub day_after: &'static str,
+    pub not_available: &'static str,
+    pub cabin_comparison: &'static str,
+    /// Marks a cabin whose search failed
+    pub price_unavailable: &'static str,
+    pub book_now: &'static str,
+    pub book_by: &'static str,
+    pub flying: &'static str,
+    pub day: &'static str,
+    pub days: &'static str,
+    pub later: &'static str,
+    pub earlier: &'static str,
+    /// Introduces the saving, e.g. "is" in "is 120.00 USD cheaper"
+    pub saving_is: &'static str,
+    pub cheaper: &'static str,
+    /// Introduces a date, e.g. "on" in "on 2025-06-03"
+    pub on_date: &'static str,
+}
+
+pub const ENGLISH: OutputLabels = OutputLabels {
+    header: "Here are some flight options:",
+    no_flights: "No flights found for the given criteria.",
+    no_flights_under: "No flights found under",
+    airline: "Airline",
+    flight_number: "Flight Number",
+    flight_numbers: "Flight Numbers",
+    marketed_as: "marketed as",
+    operated_as: "operated as",
+    departure: "Departure",
+    arrival: "Arrival",
+    duration: "Duration",
+    stops: "Stops",
+    non_stop: "Non-stop",
+    stop_count: "stop(s)",
+    one_stop: "1 stop",
+    two_plus_stops: "2+ stops",
+    cabin: "Cabin",
+    segments: "segments",
+    price: "Price",
+    price_per_hour: "Price per Hour",
+    price_per_km: "Price per km",
+    checked_bag: "Checked Bag",
+    refundable: "Refundable",
+    yes: "Yes",
+    no: "No",
+    quote_age: "Quote Age",
+    min_connection: "Min Connection",
+    also_priced: "Also Priced",
+    warning: "Warning",
+    self_transfer: "Self-transfer itinerary on separate tickets; missed connections are not protected",
+    currency_mismatch: "Priced in a different currency than requested; not comparable with the fares above",
+    price_confidence: "Price Confidence",
+    preliminary_price: "Preliminary; re-confirm the fare before booking",
+    preliminary: "Note: the provider had not finished collecting prices, so fares may change.",
+    found: "Found",
+    itineraries_showing: "itineraries, showing",
+    cabin_fallback: "No fares in the requested cabin; showing results for",
+    metro_search: "Metro-area search",
+    departing: "Departing",
+    returning: "Returning",
+    return_leg: "Return",
+    distance: "Distance",
+    weekdays: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+    months: [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ],
+    all_airports: "all airports",
+    ranking: "Ranking",
+    agent_rating: "Agent Rating",
+    price_calendar: "Price calendar",
+    no_fares: "no flights",
+    stops_plural: "stops",
+    share_cheapest: "cheapest",
+    share_on: "on",
+    booking: "Booking",
+    days_out: "days out",
+    lead_time_advice: [
+        "earlier than usual; fares often drop closer to departure",
+        "typically a good window",
+        "fares usually start climbing inside three weeks",
+        "last-minute; expect higher fares",
+    ],
+    departure_passed: "departure date has already passed",
+    day_before: "Day before",
+    selected: "selected",
+    day_after: "day after",
+    not_available: "n/a",
+    cabin_comparison: "Cabin comparison",
+    price_unavailable: "price unavailable",
+    book_now: "Consider booking now",
+    book_by: "Consider booking by",
+    flying: "Flying",
+    day: "day",
+    days: "days",
+    later: "later",
+    earlier: "earlier",
+    saving_is: "is",
+    cheaper: "cheaper",
+    on_date: "on",
+};
+
+pub const SPANISH: OutputLabels = OutputLabels {
+    header: "Estas son algunas opciones de vuelo:",
+    no_flights: "No se encontraron vuelos para los criterios indicados.",
+    no_flights_under: "No se encontraron vuelos por debajo de",
+    airline: "Aerolínea",
+    flight_number: "Número de vuelo",
+    flight_numbers: "Números de vuelo",
+    marketed_as: "comercializado como",
+    operated_as: "operado como",
+    departure: "Salida",
+    arrival: "Llegada",
+    duration: "Duración",
+    stops: "Escalas",
+    non_stop: "Directo",
+    stop_count: "escala(s)",
+    one_stop: "1 escala",
+    two_plus_stops: "2+ escalas",
+    cabin: "Cabina",
+    segments: "tramos",
+    price: "Precio",
+    price_per_hour: "Precio por hora",
+    price_per_km: "Precio por km",
+    checked_bag: "Maleta facturada",
+    refundable: "Reembolsable",
+    yes: "Sí",
+    no: "No",
+    quote_age: "Antigüedad de la tarifa",
+    min_connection: "Conexión mínima",
+    also_priced: "Otros precios",
+    warning: "Aviso",
+    self_transfer: "Itinerario con autotransbordo en billetes separados; las conexiones perdidas no están protegidas",
+    currency_mismatch: "Precio en una moneda distinta a la solicitada; no es comparable con las tarifas anteriores",
+    price_confidence: "Fiabilidad del precio",
+    preliminary_price: "Provisional; confirma la tarifa antes de reservar",
+    preliminary: "Nota: el proveedor no había terminado de recopilar precios, por lo que las tarifas pueden cambiar.",
+    found: "Se encontraron",
+    itineraries_showing: "itinerarios, se muestran",
+    cabin_fallback: "No hay tarifas en la cabina solicitada; se muestran resultados para",
+    metro_search: "Búsqueda por área metropolitana",
+    departing: "Salida",
+    returning: "Regreso",
+    return_leg: "Vuelta",
+    distance: "Distancia",
+    weekdays: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+    months: [
+        "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic",
+    ],
+    all_airports: "todos los aeropuertos",
+    ranking: "Posición",
+    agent_rating: "Valoración de la agencia",
+    price_calendar: "Calendario de precios",
+    no_fares: "sin vuelos",
+    stops_plural: "escalas",
+    share_cheapest: "mejor precio",
+    share_on: "con",
+    booking: "Reservando con",
+    days_out: "días de antelación",
+    lead_time_advice: [
+        "antes de lo habitual; las tarifas suelen bajar cerca de la salida",
+        "normalmente un buen momento",
+        "las tarifas suelen subir a menos de tres semanas",
+        "última hora; espera tarifas más altas",
+    ],
+    departure_passed: "la fecha de salida ya ha pasado",
+    day_before: "Día anterior",
+    selected: "fecha elegida",
+    day_after: "día siguiente",
+    not_available: "n/d",
+    cabin_comparison: "Comparativa de cabinas",
+    price_unavailable: "precio no disponible",
+    book_now: "Conviene reservar ya",
+    book_by: "Conviene reservar antes del",
+    flying: "Volar",
+    day: "día",
+    days: "días",
+    later: "después",
+    earlier: "antes",
+    saving_is: "sale",
+    cheaper: "más barato",
+    on_date: "el",
+};
+
+/// Embedded translation table keyed by language code
+const TRANSLATIONS: &[(&str, &OutputLabels)] = &[("en", &ENGLISH), ("es", &SPANISH)];
+
+/// Picks labels by the language part of a locale such as `es-ES`, defaulting to English
+pub fn labels_for_locale(locale: &str) -> &'static OutputLabels {
+    let language = locale.split(['-', '_']).next().unwrap_or_default();
+    TRANSLATIONS
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(language))
+        .map(|(_, labels)| *labels)
+        .unwrap_or(&ENGLISH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_for_locale() {
+        assert_eq!(labels_for_locale("es-ES").airline, "Aerolínea");
+        assert_eq!(labels_for_locale("es_MX").price, "Precio");
+        assert_eq!(labels_for_locale("en-GB").airline, "Airline");
+        // Unknown languages fall back to English
+        assert_eq!(labels_for_locale("nl-NL").header, ENGLISH.header);
+    }
+}
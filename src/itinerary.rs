@@ -0,0 +1,269 @@
+use crate::error::FlightSearchError;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Skyscanner list response. Every field is optional so partial results still
+/// parse, but a field of the wrong type fails the whole response.
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct ListResponse {
+    pub context: Option<ResponseContext>,
+    pub itineraries: Option<Itineraries>,
+    /// Some responses wrap the body in a `data` object
+    pub data: Option<Box<ListResponse>>,
+}
+
+impl ListResponse {
+    /// Deserializes a raw response, reporting type mismatches as `SchemaMismatch`
+    pub fn from_value(data: &Value) -> Result<Self, FlightSearchError> {
+        ListResponse::deserialize(data)
+            .map_err(|e| FlightSearchError::SchemaMismatch(format!("List response: {}", e)))
+    }
+
+    pub fn context(&self) -> Option<&ResponseContext> {
+        self.context
+            .as_ref()
+            .or_else(|| self.data.as_ref()?.context.as_ref())
+    }
+
+    pub fn itineraries(&self) -> Option<&Itineraries> {
+        self.itineraries
+            .as_ref()
+            .or_else(|| self.data.as_ref()?.itineraries.as_ref())
+    }
+
+    /// Every itinerary across all buckets
+    pub fn items(&self) -> impl Iterator<Item = &Itinerary> {
+        self.itineraries()
+            .into_iter()
+            .flat_map(|itineraries| &itineraries.buckets)
+            .flat_map(|bucket| &bucket.items)
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ResponseContext {
+    /// `incomplete` while the provider is still collecting prices
+    pub status: Option<String>,
+    pub total_results: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Itineraries {
+    pub buckets: Vec<Bucket>,
+    pub total_results: Option<u64>,
+}
+
+/// Group of itineraries such as "Best" or "Cheapest"
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct Bucket {
+    pub items: Vec<Itinerary>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Itinerary {
+    pub legs: Vec<Leg>,
+    pub pricing_options: Vec<PricingOption>,
+    /// Legacy total price, used when no pricing option carries one
+    pub price: Option<Price>,
+    pub is_self_transfer: bool,
+    pub virtual_interline: bool,
+}
+
+impl Itinerary {
+    pub fn first_leg(&self) -> Option<&Leg> {
+        self.legs.first()
+    }
+
+    pub fn first_pricing_option(&self) -> Option<&PricingOption> {
+        self.pricing_options.first()
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Leg {
+    pub origin: Option<Place>,
+    pub destination: Option<Place>,
+    pub departure: Option<String>,
+    pub arrival: Option<String>,
+    pub duration_in_minutes: Option<u64>,
+    pub stop_count: Option<u64>,
+    /// Minimum connection time the provider assumed, in minutes
+    pub min_connection_time: Option<u64>,
+    pub carriers: Option<Carriers>,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Place {
+    pub id: Option<String>,
+    pub display_code: Option<String>,
+    pub flight_place_id: Option<String>,
+}
+
+impl Place {
+    /// Display code, falling back to the provider's own place id
+    pub fn code(&self) -> Option<&str> {
+        self.display_code
+            .as_deref()
+            .or(self.flight_place_id.as_deref())
+            .or(self.id.as_deref())
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct Carriers {
+    pub marketing: Vec<Carrier>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct Carrier {
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Segment {
+    pub flight_number: Option<String>,
+    pub operating_flight_number: Option<String>,
+    pub origin: Option<Place>,
+    pub destination: Option<Place>,
+    pub cabin_class: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PricingOption {
+    pub price: Option<Price>,
+    pub items: Vec<BookingItem>,
+    pub fare_breakdown: Option<FareAmounts>,
+    pub baggage_fee: Option<Fee>,
+    /// Age of the cached quote in seconds
+    pub quote_age: Option<u64>,
+    pub is_refundable: Option<bool>,
+}
+
+/// A price as `{ amount, currencyCode }`, or the legacy `{ raw, currency }`
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Price {
+    #[serde(alias = "raw")]
+    pub amount: Option<f64>,
+    #[serde(alias = "currency")]
+    pub currency_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BookingItem {
+    pub url: Option<String>,
+    pub agent_id: Option<String>,
+    /// Booking agent's customer rating
+    pub rating: Option<Rating>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FareAmounts {
+    pub base: Option<f64>,
+    pub taxes: Option<f64>,
+    pub carrier_fees: Option<f64>,
+}
+
+/// A fee given either as a bare number or as `{ amount }`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Fee {
+    Amount(f64),
+    Object { amount: f64 },
+}
+
+impl Fee {
+    pub fn amount(&self) -> f64 {
+        match self {
+            Fee::Amount(amount) | Fee::Object { amount } => *amount,
+        }
+    }
+}
+
+/// A rating given either as a bare number or as `{ value, count }`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Rating {
+    Value(f64),
+    Object { value: f64 },
+}
+
+impl Rating {
+    pub fn value(&self) -> f64 {
+        match self {
+            Rating::Value(value) | Rating::Object { value } => *value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_captured_fixture() {
+        let text = include_str!("../tests/fixtures/roundtrip_list_codeshare_extras.json");
+        let response: ListResponse = serde_json::from_str(text).unwrap();
+        let items: Vec<_> = response.items().collect();
+        assert_eq!(items.len(), 2);
+        let pricing = items[0].first_pricing_option().unwrap();
+        assert_eq!(pricing.baggage_fee.as_ref().map(Fee::amount), Some(75.0));
+        assert_eq!(
+            items[1]
+                .first_pricing_option()
+                .unwrap()
+                .baggage_fee
+                .as_ref()
+                .map(Fee::amount),
+            Some(60.0)
+        );
+        let segment = &items[0].first_leg().unwrap().segments[0];
+        assert_eq!(segment.operating_flight_number.as_deref(), Some("BA196"));
+        assert_eq!(
+            segment.destination.as_ref().and_then(Place::code),
+            Some("LHR")
+        );
+    }
+
+    #[test]
+    fn test_legacy_price_alias() {
+        let price: Price = serde_json::from_str(r#"{ "raw": 889.0, "currency": "EUR" }"#).unwrap();
+        assert_eq!(price.amount, Some(889.0));
+        assert_eq!(price.currency_code.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn test_agent_rating_shapes() {
+        let item: BookingItem =
+            serde_json::from_str(r#"{ "agentId": "baus", "rating": 4.6 }"#).unwrap();
+        assert_eq!(item.rating.as_ref().map(Rating::value), Some(4.6));
+        let item: BookingItem =
+            serde_json::from_str(r#"{ "rating": { "value": 3.9, "count": 1200 } }"#).unwrap();
+        assert_eq!(item.rating.as_ref().map(Rating::value), Some(3.9));
+    }
+
+    #[test]
+    fn test_schema_drift_fails() {
+        // `legs` turned into an object
+        let data = serde_json::json!({ "itineraries": { "buckets": [
+            { "items": [{ "legs": { "departure": "2025-06-01T10:00:00" } }] }
+        ] } });
+        let err = ListResponse::from_value(&data).unwrap_err();
+        assert_eq!(err.code(), "ERR_SCHEMA_MISMATCH");
+        assert!(err.to_string().contains("List response"));
+    }
+}
@@ -0,0 +1,11 @@
+pub mod cache;
+pub mod clock;
+pub mod config;
+pub mod error;
+pub mod flight_search_tool;
+pub mod geo;
+pub mod i18n;
+pub mod itinerary;
+pub mod metrics;
+pub mod otel;
+pub mod provider;
@@ -1,17 +1,98 @@
-mod error;
-mod flight_search_tool;
-mod metrics;
-mod otel;
-
 use chrono::{Datelike, Duration, Local};
+use clap::Parser;
 use dotenv::dotenv;
-use flight_search_tool::FlightSearchTool;
+use flight_agent::clock::{Clock, SystemClock};
+use flight_agent::config::Config;
+use flight_agent::flight_search_tool::FlightSearchTool;
+use flight_agent::otel;
+use flight_agent::provider::SkyscannerProvider;
 use rig::agent::Agent;
 use rig::completion::Prompt;
 use rig::providers::openai;
 use rig::providers::openai::completion::CompletionModel;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tracing::{info, instrument};
 
+const MODEL: &str = "gpt-4.1";
+
+/// Asks an LLM agent to find flights for a trip
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// City or airport to fly from
+    #[arg(long, default_value = "Austin", value_parser = non_empty)]
+    from: String,
+    /// City or airport to fly to
+    #[arg(long, default_value = "Barcelona", value_parser = non_empty)]
+    to: String,
+    /// Departure date, e.g. 2025-06-01; defaults to 30 days from today
+    #[arg(long)]
+    depart: Option<String>,
+    /// Return date for a roundtrip
+    #[arg(long = "return")]
+    return_date: Option<String>,
+    #[arg(long, default_value_t = 1)]
+    adults: u8,
+    /// Read flight queries from stdin until EOF or "quit"
+    #[arg(long)]
+    interactive: bool,
+    /// Print the resolved configuration and exit
+    #[arg(long)]
+    diagnostics: bool,
+}
+
+fn non_empty(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    Ok(value.to_string())
+}
+
+/// Natural language request for the trip described on the command line
+fn build_prompt(cli: &Cli, clock: &dyn Clock) -> String {
+    let depart = cli.depart.clone().unwrap_or_else(|| now_plus_30d(clock));
+    let mut prompt = format!(
+        "Find me flights from {} to {} on {}",
+        cli.from, cli.to, depart
+    );
+    if let Some(return_date) = &cli.return_date {
+        prompt.push_str(&format!(", returning on {}", return_date));
+    }
+    if cli.adults > 1 {
+        prompt.push_str(&format!(" for {} adults", cli.adults));
+    }
+    prompt.push('.');
+    prompt
+}
+
+/// Whether an interactive input line ends the session
+fn is_quit(line: &str) -> bool {
+    matches!(line.trim().to_lowercase().as_str(), "quit" | "exit")
+}
+
+/// Answers one query per stdin line with the same agent until EOF or "quit"
+async fn run_interactive(agent: &Agent<CompletionModel>) -> Result<(), anyhow::Error> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    println!("Ask for flights, or type \"quit\" to leave.");
+    while let Some(line) = lines.next_line().await? {
+        if is_quit(&line) {
+            break;
+        }
+        let query = line.trim();
+        if query.is_empty() {
+            continue;
+        }
+        // A failed prompt shouldn't end the session
+        match search_flights(agent, query).await {
+            Ok(response) => println!("Agent response:\n{}", response),
+            Err(e) => eprintln!("Search failed: {}", e),
+        }
+    }
+    info!("Interactive session ended");
+    Ok(())
+}
+
 #[instrument(skip(agent))]
 async fn search_flights(
     agent: &Agent<CompletionModel>,
@@ -26,39 +107,48 @@ async fn search_flights(
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     dotenv().ok();
+    let cli = Cli::parse();
+
+    if cli.diagnostics {
+        println!(
+            "{}",
+            Config::from_env().diagnostics(SkyscannerProvider::NAME, MODEL)
+        );
+        return Ok(());
+    }
 
     // OTEL graceful shutdown on success or error exit
     let _otel_guard = otel::init_otel()?;
 
     info!("Starting flight agent");
 
+    let tool = FlightSearchTool::new();
+    // Stops sweeping expired cache entries when dropped on exit
+    let _cache_cleanup = tool.spawn_location_cache_cleanup();
+
     let openai_client = openai::Client::from_env();
 
     // Wire up model to flight search tool
     let agent = openai_client
-        .agent("gpt-4.1")
+        .agent(MODEL)
         .preamble(
             "You are a helpful assistant that can search for flights between two airports for users.",
         )
-        .tool(FlightSearchTool)
+        .tool(tool)
         .build();
 
-    let response = search_flights(
-        &agent,
-        format!(
-            "Find me flights from Austin to Barcelona on {}.",
-            now_plus_30d()
-        )
-        .as_str(),
-    )
-    .await?;
+    if cli.interactive {
+        return run_interactive(&agent).await;
+    }
+
+    let response = search_flights(&agent, &build_prompt(&cli, &SystemClock)).await?;
 
     println!("Agent response:\n{}", response);
     Ok(())
 }
 
-fn now_plus_30d() -> String {
-    let today = Local::now().date_naive();
+fn now_plus_30d(clock: &dyn Clock) -> String {
+    let today = clock.now().with_timezone(&Local).date_naive();
     let future_date = today + Duration::days(30);
     // Use a human friendly "Month Day Year" for date in prompt
     let month = future_date.format("%B").to_string();
@@ -79,3 +169,44 @@ fn day_suffix(day: u32) -> &'static str {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_prompt() {
+        let cli = Cli::parse_from([
+            "flight_agent",
+            "--from",
+            "JFK",
+            "--to",
+            "London",
+            "--depart",
+            "2025-07-01",
+            "--return",
+            "2025-07-10",
+            "--adults",
+            "2",
+        ]);
+        assert_eq!(
+            build_prompt(&cli, &SystemClock),
+            "Find me flights from JFK to London on 2025-07-01, returning on 2025-07-10 for 2 adults."
+        );
+        let cli = Cli::parse_from(["flight_agent", "--depart", "2025-07-01"]);
+        assert_eq!(
+            build_prompt(&cli, &SystemClock),
+            "Find me flights from Austin to Barcelona on 2025-07-01."
+        );
+        assert!(Cli::try_parse_from(["flight_agent", "--from", " "]).is_err());
+    }
+
+    #[test]
+    fn test_is_quit() {
+        assert!(is_quit("quit"));
+        assert!(is_quit("  QUIT\n"));
+        assert!(is_quit("exit"));
+        assert!(!is_quit("flights to quito"));
+        assert!(!is_quit(""));
+    }
+}
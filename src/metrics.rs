@@ -1,25 +1,248 @@
 use crate::error::FlightSearchError;
 use crate::otel;
 use opentelemetry::KeyValue;
-use opentelemetry::metrics::Counter;
-use std::sync::OnceLock;
+use opentelemetry::metrics::{Counter, Histogram, UpDownCounter};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Routes that get their own label on error metrics; later ones share "other"
+const MAX_ROUTE_LABELS: usize = 100;
+
+/// In-process totals mirrored from the exported counters for the exit summary
+static RUN_COUNTERS: RunCounters = RunCounters::new();
+
+static ROUTE_LABELS: RouteLabels = RouteLabels::new(MAX_ROUTE_LABELS);
+
+/// Failed flight searches, one per search whatever the cause
+pub const FLIGHT_STATUS_ERROR: &str = "flight_status_error";
+/// Failed provider list requests; one search can make several
+pub const PROVIDER_REQUEST_ERROR: &str = "flight_provider_request_error";
 
 pub fn inc_flight_status_success() {
+    RUN_COUNTERS.record_success();
     flight_status_success().add(1, &[])
 }
 
-pub fn inc_flight_status_error(status: u64, error: &FlightSearchError) {
+/// Counts one failed flight search, whatever the cause, on `flight_status_error` and
+/// in the run summary. `route` holds the resolved place ids, `None` when the search
+/// failed before both were resolved. Provider request failures are counted on their
+/// own by `inc_provider_request_error`, since one search can make several requests.
+pub fn inc_flight_search_failure(error: &FlightSearchError, route: Option<(&str, &str)>) {
+    let route = route.map_or("unresolved".to_string(), |(origin, destination)| {
+        ROUTE_LABELS.label(origin, destination)
+    });
+    let attributes = [
+        KeyValue::new("code", error.code()),
+        KeyValue::new("route", route),
+    ];
+    RUN_COUNTERS.record_error();
+    add_one(FLIGHT_STATUS_ERROR, flight_status_error(), &attributes)
+}
+
+/// Counts one failed provider list request on `flight_provider_request_error`.
+/// `origin` and `destination` are the searched place ids; the route label is capped
+/// so failures on many routes can't explode the series.
+pub fn inc_provider_request_error(
+    status: u64,
+    error: &FlightSearchError,
+    origin: &str,
+    destination: &str,
+) {
     let kind = match error {
         FlightSearchError::HttpRequestFailed(_) => "HttpRequestFailed",
         FlightSearchError::InvalidResponse(_) => "InvalidResponse",
+        FlightSearchError::InvalidArgument(_) => "InvalidArgument",
         FlightSearchError::ApiError(_) => "ApiError",
+        FlightSearchError::RateLimited(_) => "RateLimited",
+        FlightSearchError::Timeout(_) => "Timeout",
         FlightSearchError::MissingApiKey => "MissingApiKey",
+        FlightSearchError::RequestLimitExceeded(_) => "RequestLimitExceeded",
+        FlightSearchError::SchemaMismatch(_) => "SchemaMismatch",
+        FlightSearchError::PartyTooLarge { .. } => "PartyTooLarge",
+        FlightSearchError::UnsupportedFeature { .. } => "UnsupportedFeature",
     };
     let attributes = vec![
         KeyValue::new("status", status.to_string()),
         KeyValue::new("kind", kind.to_string()),
+        KeyValue::new("code", error.code()),
+        KeyValue::new("route", ROUTE_LABELS.label(origin, destination)),
+    ];
+    add_one(
+        PROVIDER_REQUEST_ERROR,
+        provider_request_error(),
+        &attributes,
+    )
+}
+
+/// Adds one to `counter`, also tallying it by `name` in tests
+#[cfg_attr(not(test), allow(unused_variables))]
+fn add_one(name: &'static str, counter: &Counter<u64>, attributes: &[KeyValue]) {
+    #[cfg(test)]
+    tally::record(name);
+    counter.add(1, attributes)
+}
+
+/// Per-thread counts of counter increments, so tests can check how often a code
+/// path records a metric without racing tests on other threads
+#[cfg(test)]
+pub(crate) mod tally {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    thread_local! {
+        static COUNTS: RefCell<HashMap<&'static str, u64>> = RefCell::new(HashMap::new());
+    }
+
+    pub(crate) fn record(name: &'static str) {
+        COUNTS.with(|counts| *counts.borrow_mut().entry(name).or_default() += 1);
+    }
+
+    /// Increments of `name` on this thread since the last `reset`
+    pub(crate) fn count(name: &str) -> u64 {
+        COUNTS.with(|counts| counts.borrow().get(name).copied().unwrap_or(0))
+    }
+
+    pub(crate) fn reset() {
+        COUNTS.with(|counts| counts.borrow_mut().clear());
+    }
+}
+
+/// Counts one retried provider request, labelled by endpoint and the status that caused it
+pub fn inc_provider_retry(endpoint: &str, status: u16) {
+    let attributes = [
+        KeyValue::new("endpoint", endpoint.to_string()),
+        KeyValue::new("status", status.to_string()),
+    ];
+    provider_retry().add(1, &attributes)
+}
+
+/// Records how long one flight search took, end to end, and whether it succeeded
+pub fn record_flight_search_duration(ms: f64, success: bool) {
+    flight_search_duration().record(ms, &[KeyValue::new("success", success)])
+}
+
+/// Records one location resolution; cache hits show the warm path, misses the
+/// auto-complete request
+pub fn record_resolve_duration(ms: f64, cache_hit: bool) {
+    resolve_duration().record(ms, &[KeyValue::new("cache_hit", cache_hit)])
+}
+
+/// Records one list request including retries, labelled by endpoint and final status
+/// (0 when no response arrived)
+pub fn record_list_duration(ms: f64, endpoint: &str, status: u16) {
+    let attributes = [
+        KeyValue::new("endpoint", endpoint.to_string()),
+        KeyValue::new("status", status.to_string()),
     ];
-    flight_status_error().add(1, &attributes)
+    list_duration().record(ms, &attributes)
+}
+
+/// Counts a search as in flight until the returned guard is dropped, so early
+/// returns and errors are never left counted
+pub fn track_in_flight() -> InFlightGuard {
+    in_flight().add(1, &[]);
+    InFlightGuard { _private: () }
+}
+
+/// Keeps one search counted in `flight_search_in_flight` while alive
+#[derive(Debug)]
+pub struct InFlightGuard {
+    _private: (),
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        in_flight().add(-1, &[]);
+    }
+}
+
+/// Milliseconds since `started`, as recorded by the duration histograms
+pub fn elapsed_ms(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1000.0
+}
+
+/// Lifetime totals of flight searches in this process
+pub fn run_summary() -> RunSummary {
+    RUN_COUNTERS.summary()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RunSummary {
+    pub successes: u64,
+    pub errors: u64,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Flight searches this run: {} succeeded, {} failed",
+            self.successes, self.errors
+        )
+    }
+}
+
+/// Hands out "ORIGIN-DESTINATION" labels for the first `max` distinct routes and
+/// "other" after that
+struct RouteLabels {
+    seen: Mutex<BTreeSet<String>>,
+    max: usize,
+}
+
+impl RouteLabels {
+    const fn new(max: usize) -> Self {
+        RouteLabels {
+            seen: Mutex::new(BTreeSet::new()),
+            max,
+        }
+    }
+
+    fn label(&self, origin: &str, destination: &str) -> String {
+        let route = format!(
+            "{}-{}",
+            origin.trim().to_uppercase(),
+            destination.trim().to_uppercase()
+        );
+        let mut seen = self.seen.lock().unwrap();
+        if seen.contains(&route) || seen.len() < self.max {
+            seen.insert(route.clone());
+            route
+        } else {
+            "other".to_string()
+        }
+    }
+}
+
+struct RunCounters {
+    successes: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl RunCounters {
+    const fn new() -> Self {
+        RunCounters {
+            successes: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+
+    fn record_success(&self) {
+        self.successes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn summary(&self) -> RunSummary {
+        RunSummary {
+            successes: self.successes.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
 }
 
 fn flight_status_success() -> &'static Counter<u64> {
@@ -38,12 +261,81 @@ fn flight_status_error() -> &'static Counter<u64> {
     COUNTER.get_or_init(|| {
         let meter = otel::get_meter();
         meter
-            .u64_counter("flight_status_error")
+            .u64_counter(FLIGHT_STATUS_ERROR)
             .with_description("Number of failed flight search executions")
             .build()
     })
 }
 
+fn provider_request_error() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        let meter = otel::get_meter();
+        meter
+            .u64_counter(PROVIDER_REQUEST_ERROR)
+            .with_description("Number of failed provider list requests")
+            .build()
+    })
+}
+
+fn provider_retry() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        let meter = otel::get_meter();
+        meter
+            .u64_counter("flight_provider_retry")
+            .with_description("Number of provider requests retried after a 429 or 5xx")
+            .build()
+    })
+}
+
+fn flight_search_duration() -> &'static Histogram<f64> {
+    static HISTOGRAM: OnceLock<Histogram<f64>> = OnceLock::new();
+    HISTOGRAM.get_or_init(|| {
+        let meter = otel::get_meter();
+        meter
+            .f64_histogram("flight_search_duration_ms")
+            .with_description("Duration of flight search tool calls")
+            .with_unit("ms")
+            .build()
+    })
+}
+
+fn in_flight() -> &'static UpDownCounter<i64> {
+    static COUNTER: OnceLock<UpDownCounter<i64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        let meter = otel::get_meter();
+        meter
+            .i64_up_down_counter("flight_search_in_flight")
+            .with_description("Number of flight searches currently running")
+            .build()
+    })
+}
+
+fn resolve_duration() -> &'static Histogram<f64> {
+    static HISTOGRAM: OnceLock<Histogram<f64>> = OnceLock::new();
+    HISTOGRAM.get_or_init(|| {
+        let meter = otel::get_meter();
+        meter
+            .f64_histogram("skyscanner_resolve_duration_ms")
+            .with_description("Duration of location resolution, cached or not")
+            .with_unit("ms")
+            .build()
+    })
+}
+
+fn list_duration() -> &'static Histogram<f64> {
+    static HISTOGRAM: OnceLock<Histogram<f64>> = OnceLock::new();
+    HISTOGRAM.get_or_init(|| {
+        let meter = otel::get_meter();
+        meter
+            .f64_histogram("skyscanner_list_duration_ms")
+            .with_description("Duration of flight list requests, including retries")
+            .with_unit("ms")
+            .build()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -70,9 +362,70 @@ mod tests {
         // Note: This test doesn't verify the actual metric values
         // as that would require a running OpenTelemetry collector
         inc_flight_status_success();
-        inc_flight_status_error(
+        inc_provider_retry("flights/roundtrip/list", 429);
+        record_flight_search_duration(1250.0, true);
+        record_resolve_duration(0.2, true);
+        record_list_duration(980.0, "flights/roundtrip/list", 200);
+        drop(track_in_flight());
+        inc_provider_request_error(
             404,
             &FlightSearchError::HttpRequestFailed("test".to_string()),
+            "AUS",
+            "BCN",
+        );
+        inc_flight_search_failure(
+            &FlightSearchError::InvalidResponse("test".to_string()),
+            Some(("AUS", "BCN")),
+        );
+        inc_flight_search_failure(&FlightSearchError::MissingApiKey, None);
+    }
+
+    #[test]
+    fn test_failure_counters_count_once_each() {
+        tally::reset();
+        inc_flight_search_failure(&FlightSearchError::MissingApiKey, None);
+        assert_eq!(tally::count(FLIGHT_STATUS_ERROR), 1);
+        assert_eq!(tally::count(PROVIDER_REQUEST_ERROR), 0);
+
+        tally::reset();
+        inc_provider_request_error(
+            500,
+            &FlightSearchError::ApiError("test".to_string()),
+            "AUS",
+            "BCN",
+        );
+        assert_eq!(tally::count(PROVIDER_REQUEST_ERROR), 1);
+        assert_eq!(tally::count(FLIGHT_STATUS_ERROR), 0);
+    }
+
+    #[test]
+    fn test_route_labels_are_capped() {
+        let labels = RouteLabels::new(2);
+        assert_eq!(labels.label("aus", "bcn"), "AUS-BCN");
+        assert_eq!(labels.label("JFK", "LHR"), "JFK-LHR");
+        assert_eq!(labels.label("MAD", "LIS"), "other");
+        // Routes seen before the cap keep their label
+        assert_eq!(labels.label("AUS", "BCN"), "AUS-BCN");
+    }
+
+    #[test]
+    fn test_run_counters_summary() {
+        let counters = RunCounters::new();
+        counters.record_success();
+        counters.record_success();
+        counters.record_error();
+
+        let summary = counters.summary();
+        assert_eq!(
+            summary,
+            RunSummary {
+                successes: 2,
+                errors: 1
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "Flight searches this run: 2 succeeded, 1 failed"
         );
     }
 }
@@ -1,23 +1,35 @@
+use crate::config::{Config, OtlpCompression};
+use crate::metrics;
 use anyhow::anyhow;
 use opentelemetry::global;
 use opentelemetry::metrics::Meter;
 use opentelemetry::propagation::TextMapCompositePropagator;
 use opentelemetry::trace::TracerProvider;
 use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
-use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_otlp::{Compression, WithExportConfig, WithTonicConfig};
 use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
 use opentelemetry_sdk::logs::{BatchLogProcessor, SdkLoggerProvider};
 use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
 use opentelemetry_sdk::propagation::{BaggagePropagator, TraceContextPropagator};
-use opentelemetry_sdk::trace::{BatchSpanProcessor, SdkTracerProvider};
+use opentelemetry_sdk::trace::{BatchSpanProcessor, SdkTracerProvider, SpanData, SpanExporter};
 use std::env;
 use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
-use tracing::subscriber;
+use tonic::metadata::{MetadataKey, MetadataMap};
+use tracing::{Subscriber, info, subscriber};
 use tracing_opentelemetry::OpenTelemetryLayer;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::Layer;
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Consecutive failed span exports before warning that telemetry is being lost
+const EXPORT_FAILURE_WARN_THRESHOLD: u64 = 3;
+
+/// Failed OTLP span exports in this process
+static SPAN_EXPORT_HEALTH: ExportHealth = ExportHealth::new();
 
 /// Initialize OpenTelemetry and return a guard that ensures proper cleanup
 pub fn init_otel() -> Result<OtelGuard, anyhow::Error> {
@@ -39,9 +51,81 @@ pub struct OtelGuard {
 /// Calls `providers.shutdown()` on success of failure
 impl Drop for OtelGuard {
     fn drop(&mut self) {
+        // Logged before shutdown so the summary is still exported
+        info!("{}", metrics::run_summary());
         if let Err(e) = self.providers.shutdown() {
             eprintln!("Error during OpenTelemetry shutdown: {}", e);
         }
+        let failures = SPAN_EXPORT_HEALTH.total_failures();
+        if failures > 0 {
+            eprintln!("{} OTLP span exports failed this run", failures);
+        }
+    }
+}
+
+/// Export failure counts, so a down collector doesn't lose telemetry silently
+#[derive(Debug)]
+struct ExportHealth {
+    consecutive_failures: AtomicU64,
+    total_failures: AtomicU64,
+}
+
+impl ExportHealth {
+    const fn new() -> Self {
+        ExportHealth {
+            consecutive_failures: AtomicU64::new(0),
+            total_failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Records one export result, returning true when this failure completes a
+    /// streak of `EXPORT_FAILURE_WARN_THRESHOLD`
+    fn record(&self, result: &OTelSdkResult) -> bool {
+        if result.is_ok() {
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            return false;
+        }
+        self.total_failures.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1
+            == EXPORT_FAILURE_WARN_THRESHOLD
+    }
+
+    fn total_failures(&self) -> u64 {
+        self.total_failures.load(Ordering::Relaxed)
+    }
+}
+
+/// Span exporter that counts failed exports and warns once failures pile up
+#[derive(Debug)]
+struct CountingSpanExporter<E> {
+    inner: E,
+    health: &'static ExportHealth,
+}
+
+impl<E: SpanExporter> SpanExporter for CountingSpanExporter<E> {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let result = self.inner.export(batch).await;
+        if self.health.record(&result) {
+            // Written to stderr, as tracing may itself be exporting to the failing collector
+            eprintln!(
+                "WARNING: {} consecutive OTLP span exports failed, telemetry is being lost: {}",
+                EXPORT_FAILURE_WARN_THRESHOLD,
+                result.as_ref().unwrap_err()
+            );
+        }
+        result
+    }
+
+    fn shutdown(&mut self) -> OTelSdkResult {
+        self.inner.shutdown()
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource)
     }
 }
 
@@ -65,9 +149,7 @@ impl OtelProviders {
             .add_directive("rig-core=off".parse()?)
             .add_directive("reqwest=off".parse()?);
         let log_layer = otel_layer.with_filter(filter_otel);
-        let fmt_layer = tracing_subscriber::fmt::layer()
-            .with_thread_names(true)
-            .with_filter(EnvFilter::new("info").add_directive("opentelemetry=info".parse()?));
+        let fmt_layer = stdout_layer(Config::from_env().stdout_logs)?;
 
         let trace_provider = init_traces()?;
         // Create a new OpenTelemetryTracingBridge using the TracerProvider.
@@ -113,9 +195,101 @@ impl OtelProviders {
     }
 }
 
+/// Stdout log layer, left out when `FLIGHT_SEARCH_STDOUT_LOGS` is off so
+/// production output isn't doubled when OTLP is the real sink
+fn stdout_layer<S>(enabled: bool) -> Result<Option<impl Layer<S>>, anyhow::Error>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    if !enabled {
+        return Ok(None);
+    }
+    Ok(Some(
+        tracing_subscriber::fmt::layer()
+            .with_thread_names(true)
+            .with_filter(EnvFilter::new("info").add_directive("opentelemetry=info".parse()?)),
+    ))
+}
+
 fn get_service() -> &'static String {
     static SERVICE: OnceLock<String> = OnceLock::new();
-    SERVICE.get_or_init(|| env::var("OTEL_SERVICE_NAME").unwrap_or("otel-service".to_owned()))
+    SERVICE.get_or_init(|| service_name(env::var("OTEL_SERVICE_NAME").ok()))
+}
+
+/// Falls back to the default service name when the variable is unset or blank
+fn service_name(value: Option<String>) -> String {
+    value
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+        .unwrap_or("otel-service".to_owned())
+}
+
+/// Validated `OTEL_EXPORTER_OTLP_ENDPOINT`, or `None` to export to stdout
+fn otlp_endpoint() -> Result<Option<String>, anyhow::Error> {
+    let config = Config::from_env();
+    config
+        .otlp_endpoint
+        .map(|endpoint| normalize_endpoint(&endpoint, &config.otlp_default_scheme))
+        .transpose()
+}
+
+/// Prepends `default_scheme` to scheme-less endpoints such as `collector:4317`,
+/// which tonic otherwise rejects with an unhelpful error, then validates the URL
+fn normalize_endpoint(endpoint: &str, default_scheme: &str) -> Result<String, anyhow::Error> {
+    let endpoint = endpoint.trim();
+    let endpoint = if endpoint.contains("://") {
+        endpoint.to_string()
+    } else {
+        format!("{}://{}", default_scheme, endpoint)
+    };
+    let url = reqwest::Url::parse(&endpoint)
+        .map_err(|e| anyhow!("Invalid OTEL_EXPORTER_OTLP_ENDPOINT '{}': {}", endpoint, e))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(anyhow!(
+            "Invalid OTEL_EXPORTER_OTLP_ENDPOINT '{}': expected http(s)://host[:port]",
+            endpoint
+        ));
+    }
+    Ok(endpoint)
+}
+
+/// Exporter compression for the configured setting, `None` to send uncompressed
+fn otlp_compression(setting: OtlpCompression) -> Option<Compression> {
+    match setting {
+        OtlpCompression::Gzip => Some(Compression::Gzip),
+        OtlpCompression::None => None,
+    }
+}
+
+/// gRPC metadata for the configured `OTEL_EXPORTER_OTLP_HEADERS`
+fn otlp_metadata(headers: &[(String, String)]) -> Result<MetadataMap, anyhow::Error> {
+    let mut metadata = MetadataMap::with_capacity(headers.len());
+    for (key, value) in headers {
+        let name = MetadataKey::from_bytes(key.as_bytes())
+            .map_err(|_| anyhow!("Invalid OTEL_EXPORTER_OTLP_HEADERS name '{}'", key))?;
+        let value = value
+            .parse()
+            .map_err(|_| anyhow!("Invalid OTEL_EXPORTER_OTLP_HEADERS value for '{}'", key))?;
+        metadata.insert(name, value);
+    }
+    Ok(metadata)
+}
+
+/// Tonic exporter builder pointed at `endpoint` with the configured compression
+/// and headers
+fn tonic_exporter<B: WithExportConfig + WithTonicConfig>(
+    builder: B,
+    endpoint: String,
+) -> Result<B, anyhow::Error> {
+    let config = Config::from_env();
+    let mut builder = builder.with_endpoint(endpoint);
+    if let Some(compression) = otlp_compression(config.otlp_compression) {
+        builder = builder.with_compression(compression);
+    }
+    if !config.otlp_headers.is_empty() {
+        builder = builder.with_metadata(otlp_metadata(&config.otlp_headers)?);
+    }
+    Ok(builder)
 }
 
 fn get_resource() -> Resource {
@@ -138,7 +312,7 @@ fn init_traces() -> Result<SdkTracerProvider, anyhow::Error> {
     ]);
     global::set_text_map_propagator(composite_propagator);
 
-    let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT");
+    let otlp_endpoint = otlp_endpoint()?;
 
     // Build the trace provider with the appropriate exporter
     let batch_config = opentelemetry_sdk::trace::BatchConfigBuilder::default()
@@ -146,12 +320,14 @@ fn init_traces() -> Result<SdkTracerProvider, anyhow::Error> {
         .with_scheduled_delay(Duration::from_secs(1))
         .with_max_export_batch_size(100)
         .build();
-    let provider = if otlp_endpoint.is_ok() {
-        let exporter = opentelemetry_otlp::SpanExporter::builder()
-            .with_tonic()
-            .with_endpoint(otlp_endpoint?)
-            .build()
-            .expect("Failed to create span exporter");
+    let provider = if let Some(otlp_endpoint) = otlp_endpoint {
+        let builder = opentelemetry_otlp::SpanExporter::builder().with_tonic();
+        let exporter = CountingSpanExporter {
+            inner: tonic_exporter(builder, otlp_endpoint)?
+                .build()
+                .expect("Failed to create span exporter"),
+            health: &SPAN_EXPORT_HEALTH,
+        };
         SdkTracerProvider::builder()
             .with_span_processor(BatchSpanProcessor::new(exporter, batch_config))
             .with_resource(get_resource())
@@ -171,11 +347,9 @@ fn init_traces() -> Result<SdkTracerProvider, anyhow::Error> {
 }
 
 fn init_metrics() -> Result<SdkMeterProvider, anyhow::Error> {
-    let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT");
-    let provider = if otlp_endpoint.is_ok() {
-        let exporter = opentelemetry_otlp::MetricExporter::builder()
-            .with_tonic()
-            .with_endpoint(otlp_endpoint?)
+    let provider = if let Some(otlp_endpoint) = otlp_endpoint()? {
+        let builder = opentelemetry_otlp::MetricExporter::builder().with_tonic();
+        let exporter = tonic_exporter(builder, otlp_endpoint)?
             .build()
             .expect("Failed to create metric exporter");
         SdkMeterProvider::builder()
@@ -202,14 +376,11 @@ fn init_metrics() -> Result<SdkMeterProvider, anyhow::Error> {
 }
 
 fn init_logs() -> Result<SdkLoggerProvider, anyhow::Error> {
-    let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT");
-
     // Build the logger provider with the appropriate exporter
-    let batch_processor = if otlp_endpoint.is_ok() {
+    let batch_processor = if let Some(otlp_endpoint) = otlp_endpoint()? {
         // Setup logger provider with OTLP exporter using gRPC
-        let otlp_exporter = opentelemetry_otlp::LogExporter::builder()
-            .with_tonic()
-            .with_endpoint(otlp_endpoint?) // Adjust as needed
+        let builder = opentelemetry_otlp::LogExporter::builder().with_tonic();
+        let otlp_exporter = tonic_exporter(builder, otlp_endpoint)?
             .build()
             .expect("Failed to build OTLP log exporter");
         BatchLogProcessor::builder(otlp_exporter).build()
@@ -238,6 +409,17 @@ mod tests {
         assert!(std::ptr::eq(service1, service2));
     }
 
+    #[test]
+    fn test_service_name_fallback() {
+        assert_eq!(service_name(None), "otel-service");
+        assert_eq!(service_name(Some("".to_owned())), "otel-service");
+        assert_eq!(service_name(Some("   ".to_owned())), "otel-service");
+        assert_eq!(
+            service_name(Some("flight_agent".to_owned())),
+            "flight_agent"
+        );
+    }
+
     #[test]
     fn test_get_meter_once_lock() {
         // Test that get_meter() returns the same instance across multiple calls
@@ -245,4 +427,128 @@ mod tests {
         let meter2 = get_meter();
         assert!(std::ptr::eq(meter1, meter2));
     }
+
+    #[test]
+    fn test_stdout_layer_toggle() {
+        type Registry = tracing_subscriber::Registry;
+        assert!(stdout_layer::<Registry>(true).unwrap().is_some());
+        assert!(stdout_layer::<Registry>(false).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_otlp_compression() {
+        assert_eq!(
+            otlp_compression(OtlpCompression::Gzip),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(otlp_compression(OtlpCompression::None), None);
+        // Gzip needs the `gzip-tonic` feature, otherwise building fails
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint("http://localhost:4317")
+            .with_compression(Compression::Gzip)
+            .build();
+        assert!(exporter.is_ok());
+    }
+
+    #[test]
+    fn test_otlp_metadata() {
+        let headers = vec![
+            ("authorization".to_string(), "Bearer abc123".to_string()),
+            ("x-team".to_string(), "flights".to_string()),
+        ];
+        let metadata = otlp_metadata(&headers).unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata.get("authorization").unwrap(), "Bearer abc123");
+        assert_eq!(metadata.get("x-team").unwrap(), "flights");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint("http://localhost:4317")
+            .with_metadata(metadata)
+            .build();
+        assert!(exporter.is_ok());
+
+        let invalid = vec![("bad header".to_string(), "x".to_string())];
+        assert!(
+            otlp_metadata(&invalid)
+                .unwrap_err()
+                .to_string()
+                .contains("'bad header'")
+        );
+    }
+
+    #[derive(Debug)]
+    struct FailingExporter;
+
+    impl SpanExporter for FailingExporter {
+        async fn export(&self, _batch: Vec<SpanData>) -> OTelSdkResult {
+            Err(opentelemetry_sdk::error::OTelSdkError::InternalFailure(
+                "collector unavailable".to_string(),
+            ))
+        }
+    }
+
+    #[test]
+    fn test_counting_span_exporter() {
+        static HEALTH: ExportHealth = ExportHealth::new();
+        let exporter = CountingSpanExporter {
+            inner: FailingExporter,
+            health: &HEALTH,
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        for _ in 0..4 {
+            assert!(runtime.block_on(exporter.export(Vec::new())).is_err());
+        }
+        assert_eq!(HEALTH.total_failures(), 4);
+        assert_eq!(HEALTH.consecutive_failures.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_export_health_warns_once_per_streak() {
+        let health = ExportHealth::new();
+        let failed: OTelSdkResult = Err(opentelemetry_sdk::error::OTelSdkError::InternalFailure(
+            "collector unavailable".to_string(),
+        ));
+        let warnings: Vec<_> = (0..4).map(|_| health.record(&failed)).collect();
+        assert_eq!(warnings, vec![false, false, true, false]);
+        // A successful export ends the streak but keeps the total
+        assert!(!health.record(&Ok(())));
+        assert_eq!(health.total_failures(), 4);
+        assert!(!health.record(&failed));
+        assert!(!health.record(&failed));
+        assert!(health.record(&failed));
+    }
+
+    #[test]
+    fn test_normalize_endpoint() {
+        assert_eq!(
+            normalize_endpoint("collector:4317", "http").unwrap(),
+            "http://collector:4317"
+        );
+        assert_eq!(
+            normalize_endpoint("collector:4317", "https").unwrap(),
+            "https://collector:4317"
+        );
+        assert_eq!(
+            normalize_endpoint(" https://otel.example.com:4317 ", "http").unwrap(),
+            "https://otel.example.com:4317"
+        );
+        assert!(
+            normalize_endpoint("ftp://collector:4317", "http")
+                .unwrap_err()
+                .to_string()
+                .contains("expected http(s)://host[:port]")
+        );
+        assert!(
+            normalize_endpoint("collector:notaport", "http")
+                .unwrap_err()
+                .to_string()
+                .starts_with("Invalid OTEL_EXPORTER_OTLP_ENDPOINT")
+        );
+    }
 }
@@ -0,0 +1,1259 @@
+use crate::clock::jitter;
+use crate::config::{Config, RequestLogLevel};
+use crate::error::FlightSearchError;
+use crate::flight_search_tool::{
+    FareBreakdown, FlightOption, LegDetails, PriceConfidence, format_duration,
+};
+use crate::geo::Coordinates;
+use crate::itinerary::{Itinerary, Leg, ListResponse, Place, Rating};
+use crate::metrics::{
+    elapsed_ms, inc_provider_request_error, inc_provider_retry, record_list_duration,
+};
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{Level, debug, error, info, warn};
+
+/// Optional search features a flight data provider can serve
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProviderCapabilities {
+    pub price_calendar: bool,
+    pub nearby_prices: bool,
+    pub multi_currency: bool,
+    pub self_transfer_filter: bool,
+    pub quote_age: bool,
+    pub cabin_comparison: bool,
+    /// Largest party one search can book, if the provider has a limit
+    pub max_passengers: Option<u32>,
+}
+
+/// Provider ids for a resolved airport or city
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct Location {
+    pub sky_id: String,
+    pub entity_id: String,
+    /// City name when the ids cover every airport of a metro area
+    pub metro_name: Option<String>,
+    /// Where the place is, when the auto-complete suggestion includes it
+    pub coordinates: Option<Coordinates>,
+}
+
+impl Location {
+    /// Sky id when the location has one, else its entity id, as the list request searches it
+    pub fn place_id(&self) -> &str {
+        if self.sky_id.is_empty() {
+            &self.entity_id
+        } else {
+            &self.sky_id
+        }
+    }
+}
+
+/// Whether a search covers one leg or an outbound and return leg
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TripType {
+    #[default]
+    OneWay,
+    RoundTrip,
+}
+
+impl TripType {
+    /// Parses `one_way`/`round_trip`; without a value, a return date implies a roundtrip
+    pub fn parse(value: Option<&str>, has_return_date: bool) -> Result<Self, FlightSearchError> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            Some("one_way") => Ok(TripType::OneWay),
+            Some("round_trip") => Ok(TripType::RoundTrip),
+            Some(other) => Err(FlightSearchError::InvalidArgument(format!(
+                "Unsupported trip_type value: {}",
+                other
+            ))),
+            None if has_return_date => Ok(TripType::RoundTrip),
+            None => Ok(TripType::OneWay),
+        }
+    }
+}
+
+/// One list search in provider-neutral terms; each provider maps it to its own request
+#[derive(Debug, Clone)]
+pub struct SearchParams {
+    pub trip_type: TripType,
+    pub origin: Location,
+    pub destination: Location,
+    pub departure_date: NaiveDate,
+    /// Only set for roundtrips
+    pub return_date: Option<NaiveDate>,
+    pub cabin: String,
+    pub adults: u8,
+    pub children: u8,
+    pub infants: u8,
+    pub market: String,
+    pub currency: String,
+    pub locale: String,
+}
+
+impl SearchParams {
+    /// The same search with the travel dates moved by `days`, keeping the trip length
+    pub fn shifted(&self, days: i64) -> Self {
+        let shift = |date: NaiveDate| date + chrono::Duration::days(days);
+        SearchParams {
+            departure_date: shift(self.departure_date),
+            return_date: self.return_date.map(shift),
+            ..self.clone()
+        }
+    }
+}
+
+/// Caps the number of outbound requests a single logical search may issue. Providers
+/// claim one request per HTTP attempt, retries included, so the retry policy can't
+/// take a search past its cap.
+#[derive(Debug)]
+pub struct RequestBudget {
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl RequestBudget {
+    pub fn new(max: usize) -> Self {
+        RequestBudget {
+            max,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Claims one request, failing once the cap has been reached
+    pub fn acquire(&self) -> Result<(), FlightSearchError> {
+        let used = self.used.fetch_add(1, Ordering::SeqCst);
+        if used >= self.max {
+            error!("Search exceeded its limit of {} requests", self.max);
+            return Err(FlightSearchError::RequestLimitExceeded(self.max));
+        }
+        Ok(())
+    }
+}
+
+/// Provider-neutral outcome of one list search
+#[derive(Debug, Default)]
+pub struct ProviderResults {
+    /// Every itinerary found, in the provider's order and before the tool's filters
+    pub options: Vec<FlightOption>,
+    /// False while the provider is still collecting prices
+    pub complete: bool,
+    /// Itineraries the provider matched, when it reports a count
+    pub total_results: Option<u64>,
+}
+
+/// Boxed provider future; `Sync` as well as `Send` because rig requires it of tool calls
+pub type ProviderFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T, FlightSearchError>> + Send + Sync + 'a>>;
+
+/// Source of flight data behind the search tool
+pub trait FlightProvider: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+    fn capabilities(&self) -> ProviderCapabilities;
+    /// Resolves an airport code or city name to provider ids, claiming each request
+    /// it sends from `budget`
+    fn resolve_location<'a>(
+        &'a self,
+        query: &'a str,
+        budget: &'a RequestBudget,
+    ) -> ProviderFuture<'a, Location>;
+    /// Runs one list search and returns the itineraries found, claiming each request
+    /// it sends from `budget`
+    fn search<'a>(
+        &'a self,
+        params: &'a SearchParams,
+        budget: &'a RequestBudget,
+    ) -> ProviderFuture<'a, ProviderResults>;
+}
+
+/// Skyscanner via RapidAPI
+#[derive(Debug, Clone)]
+pub struct SkyscannerProvider {
+    api_key: Option<String>,
+    log_requests: RequestLogLevel,
+    allow_entity_only: bool,
+    timeout: Duration,
+    retry: RetryPolicy,
+    api_host: String,
+    base_url: String,
+    not_found_as_empty: bool,
+    cache_key_header: Option<String>,
+}
+
+/// How often and how long a failing request is retried
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    max_retries: u32,
+    /// First backoff delay when the provider doesn't send `Retry-After`; doubles per retry
+    base_delay: Duration,
+    /// Cap on the total time spent waiting between attempts
+    budget: Duration,
+    /// Add up to half the backoff at random so concurrent searches don't retry in step
+    jitter: bool,
+}
+
+/// Retries made so far for one request and the time spent waiting on them
+#[derive(Debug)]
+struct RetryState {
+    policy: RetryPolicy,
+    retries: u32,
+    waited: Duration,
+}
+
+impl RetryState {
+    fn new(policy: RetryPolicy) -> Self {
+        RetryState {
+            policy,
+            retries: 0,
+            waited: Duration::ZERO,
+        }
+    }
+
+    /// Delay before the next attempt, preferring the provider's `Retry-After`, or
+    /// `None` once the retries are used up or the wait would overrun the budget
+    fn next_delay(&mut self, retry_after: Option<Duration>) -> Option<Duration> {
+        if self.retries >= self.policy.max_retries {
+            return None;
+        }
+        let delay = retry_after.unwrap_or_else(|| {
+            let backoff = self.policy.base_delay * 2u32.pow(self.retries);
+            if self.policy.jitter {
+                backoff + jitter(backoff / 2)
+            } else {
+                backoff
+            }
+        });
+        if self.waited + delay > self.policy.budget {
+            return None;
+        }
+        self.retries += 1;
+        self.waited += delay;
+        Some(delay)
+    }
+}
+
+/// Rate limiting and server errors are usually transient
+fn is_retryable(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// A 429 gets its own error so callers can back off instead of treating it as a failure
+fn status_error(status: reqwest::StatusCode, message: String) -> FlightSearchError {
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        FlightSearchError::RateLimited(message)
+    } else {
+        FlightSearchError::ApiError(message)
+    }
+}
+
+/// Sky id of a searched place when the location has one, else its entity id
+fn searched_place<'a>(
+    query_params: &'a HashMap<&'static str, String>,
+    sky_id: &str,
+    entity_id: &str,
+) -> &'a str {
+    query_params
+        .get(sky_id)
+        .or_else(|| query_params.get(entity_id))
+        .map_or("", String::as_str)
+}
+
+/// Deterministic hash of an endpoint and its query parameters, so identical searches
+/// share a key whatever order the parameters were built in. FNV-1a keeps the hash
+/// stable across runs.
+fn cache_key<Q: Serialize + ?Sized>(endpoint: &str, query: &Q) -> String {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    let params = match serde_json::to_value(query) {
+        Ok(Value::Object(map)) => {
+            let sorted: BTreeMap<String, Value> = map.into_iter().collect();
+            serde_json::to_string(&sorted).unwrap_or_default()
+        }
+        Ok(value) => value.to_string(),
+        Err(_) => String::new(),
+    };
+    let hash = format!("{}?{}", endpoint, params)
+        .bytes()
+        .fold(FNV_OFFSET, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        });
+    format!("{:016x}", hash)
+}
+
+/// Whether a 404 body reports a search without flights rather than a missing endpoint,
+/// which the RapidAPI gateway answers with `{"message": "Endpoint '...' does not exist"}`
+fn not_found_means_no_results(body: &str) -> bool {
+    let message = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|v| v.get("message")?.as_str().map(str::to_lowercase));
+    !message.is_some_and(|m| m.starts_with("endpoint"))
+}
+
+/// `Retry-After` given in seconds; HTTP dates are ignored
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+impl SkyscannerProvider {
+    pub const NAME: &'static str = "skyscanner";
+
+    pub fn from_config(config: &Config) -> Self {
+        SkyscannerProvider {
+            api_key: config.rapidapi_key.clone(),
+            log_requests: config.log_requests,
+            allow_entity_only: config.allow_entity_only_locations,
+            timeout: Duration::from_secs(config.request_timeout_secs),
+            retry: RetryPolicy {
+                max_retries: config.max_retries,
+                base_delay: Duration::from_millis(config.retry_base_delay_ms),
+                budget: Duration::from_millis(config.retry_budget_ms),
+                jitter: true,
+            },
+            api_host: config.skyscanner_api_host.clone(),
+            base_url: config.skyscanner_base_url.clone(),
+            not_found_as_empty: config.skyscanner_not_found_as_empty,
+            cache_key_header: config.cache_key_header.clone(),
+        }
+    }
+
+    fn api_key(&self) -> Result<&str, FlightSearchError> {
+        self.api_key
+            .as_deref()
+            .ok_or(FlightSearchError::MissingApiKey)
+    }
+
+    /// RapidAPI headers for a request, plus its cache key when a header is configured
+    fn headers<Q: Serialize + ?Sized>(
+        &self,
+        api_key: &str,
+        endpoint: &str,
+        query: &Q,
+    ) -> Result<reqwest::header::HeaderMap, FlightSearchError> {
+        let value = |name: &str, value: &str| {
+            value.parse().map_err(|_| {
+                FlightSearchError::InvalidResponse(format!("Invalid {} header value", name))
+            })
+        };
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("X-RapidAPI-Host", value("X-RapidAPI-Host", &self.api_host)?);
+        headers.insert("X-RapidAPI-Key", value("X-RapidAPI-Key", api_key)?);
+        if let Some(name) = &self.cache_key_header {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                FlightSearchError::InvalidResponse(format!(
+                    "Invalid cache key header name {}",
+                    name
+                ))
+            })?;
+            headers.insert(name, value("cache key", &cache_key(endpoint, query))?);
+        }
+        Ok(headers)
+    }
+
+    /// Sends a GET, retrying rate-limited and server errors per the retry policy.
+    /// Every attempt claims a request from `budget`, so retries stop at the search's cap.
+    /// Only safe because every Skyscanner call is an idempotent GET.
+    async fn get_with_retries<Q: Serialize + ?Sized>(
+        &self,
+        endpoint: &str,
+        query: &Q,
+        budget: &RequestBudget,
+    ) -> Result<reqwest::Response, FlightSearchError> {
+        let api_key = self.api_key()?;
+        let headers = self.headers(api_key, endpoint, query)?;
+        let mut retries = RetryState::new(self.retry);
+        loop {
+            budget.acquire()?;
+            let response = http_client()
+                .get(format!("{}/{}", self.base_url, endpoint))
+                .headers(headers.clone())
+                .query(query)
+                .timeout(self.timeout)
+                .send()
+                .await
+                .map_err(request_error)?;
+            let status = response.status();
+            if is_retryable(status)
+                && let Some(delay) = retries.next_delay(retry_after(&response))
+            {
+                warn!(
+                    "Skyscanner {} returned {}, retrying in {:?}",
+                    endpoint, status, delay
+                );
+                inc_provider_retry(endpoint, status.as_u16());
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            return Ok(response);
+        }
+    }
+
+    async fn fetch_location(
+        &self,
+        query: &str,
+        budget: &RequestBudget,
+    ) -> Result<Location, FlightSearchError> {
+        let response = self
+            .get_with_retries("flights/auto-complete", &[("query", query)], budget)
+            .await?;
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+        if !status.is_success() {
+            return Err(status_error(
+                status,
+                format!("Auto-complete failed: {}: {}", status, text),
+            ));
+        }
+        let data: Value = serde_json::from_str(&text)
+            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+        parse_location(&data, query, self.allow_entity_only)
+    }
+
+    /// Calls the Skyscanner list endpoint for the trip type and parses the response
+    async fn fetch_flight_list(
+        &self,
+        params: &SearchParams,
+        budget: &RequestBudget,
+    ) -> Result<ListResponse, FlightSearchError> {
+        self.api_key()?;
+        let endpoint = match params.trip_type {
+            TripType::OneWay => "flights/one-way/list",
+            TripType::RoundTrip => "flights/roundtrip/list",
+        };
+        let query_params = list_query_params(params);
+        for (level, line) in request_log_lines(self.log_requests, endpoint, &query_params) {
+            if level == Level::DEBUG {
+                debug!("{}", line);
+            } else {
+                info!("{}", line);
+            }
+        }
+        let origin = searched_place(&query_params, "origin", "originId");
+        let destination = searched_place(&query_params, "destination", "destinationId");
+        let started = Instant::now();
+        let response = self
+            .get_with_retries(endpoint, &query_params, budget)
+            .await
+            .inspect_err(|error| {
+                record_list_duration(elapsed_ms(started), endpoint, 0);
+                if let FlightSearchError::Timeout(_) = error {
+                    // No response status to report
+                    inc_provider_request_error(0, error, origin, destination);
+                }
+            })?;
+        let status = response.status();
+        record_list_duration(elapsed_ms(started), endpoint, status.as_u16());
+        let text = response
+            .text()
+            .await
+            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+        if status == reqwest::StatusCode::NOT_FOUND
+            && self.not_found_as_empty
+            && not_found_means_no_results(&text)
+        {
+            info!(
+                "Skyscanner {} returned 404, treating as no flights",
+                endpoint
+            );
+            return Ok(ListResponse::default());
+        }
+        if !status.is_success() {
+            let error = status_error(status, format!("Status: {}, Response: {}", status, text));
+            error!(
+                code = error.code(),
+                "Skyscanner API call failed with status {}: response: {}", status, text
+            );
+            inc_provider_request_error(status.as_u16() as u64, &error, origin, destination);
+            return Err(error);
+        }
+        // Parse Skyscanner response and map to FlightOption(s)
+        let data: Value = serde_json::from_str(&text)
+            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+        debug!("Parsed Skyscanner response: {:?}", data);
+        ListResponse::from_value(&data)
+    }
+}
+
+impl FlightProvider for SkyscannerProvider {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            price_calendar: true,
+            nearby_prices: true,
+            multi_currency: true,
+            self_transfer_filter: true,
+            quote_age: true,
+            cabin_comparison: true,
+            max_passengers: Some(9),
+        }
+    }
+
+    fn resolve_location<'a>(
+        &'a self,
+        query: &'a str,
+        budget: &'a RequestBudget,
+    ) -> ProviderFuture<'a, Location> {
+        Box::pin(self.fetch_location(query, budget))
+    }
+
+    fn search<'a>(
+        &'a self,
+        params: &'a SearchParams,
+        budget: &'a RequestBudget,
+    ) -> ProviderFuture<'a, ProviderResults> {
+        Box::pin(async move {
+            let response = self.fetch_flight_list(params, budget).await?;
+            Ok(skyscanner_results(&response, &params.currency))
+        })
+    }
+}
+
+/// Process-wide HTTP client, so repeated searches reuse pooled connections and TLS sessions
+fn http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .pool_idle_timeout(Duration::from_secs(90))
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()
+            .expect("HTTP client configuration is valid")
+    })
+}
+
+/// Keeps timeouts apart from other transport failures so they can be counted separately
+fn request_error(error: reqwest::Error) -> FlightSearchError {
+    if error.is_timeout() {
+        FlightSearchError::Timeout(error.to_string())
+    } else {
+        FlightSearchError::HttpRequestFailed(error.to_string())
+    }
+}
+
+/// Picks a location from an auto-complete response. A city whose name matches the
+/// query wins over its individual airports so every airport of the metro area is
+/// searched; otherwise the first suggestion carrying both flight ids is used.
+/// Suggestions with an entity id but no sky id are only used, as a last resort,
+/// when `allow_entity_only` is set, since the list endpoint accepts `originId` alone.
+pub fn parse_location(
+    data: &Value,
+    query: &str,
+    allow_entity_only: bool,
+) -> Result<Location, FlightSearchError> {
+    // Use inputSuggest array per schema
+    let locations: Vec<Location> = data
+        .get("inputSuggest")
+        .and_then(|d| d.as_array())
+        .map(|suggestions| suggestions.iter().filter_map(suggestion_location).collect())
+        .unwrap_or_default();
+    let query = query.trim();
+    let metro = locations.iter().position(|location| {
+        !location.sky_id.is_empty()
+            && location
+                .metro_name
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(query))
+    });
+    let complete = locations
+        .iter()
+        .position(|location| !location.sky_id.is_empty());
+    let entity_only = if allow_entity_only && !locations.is_empty() {
+        Some(0)
+    } else {
+        None
+    };
+    metro
+        .or(complete)
+        .or(entity_only)
+        .map(|pos| locations[pos].clone())
+        .ok_or_else(|| {
+            FlightSearchError::InvalidResponse(
+                "No valid airport found in auto-complete response".to_string(),
+            )
+        })
+}
+
+fn suggestion_location(item: &Value) -> Option<Location> {
+    let params = item
+        .get("navigation")
+        .and_then(|nav| nav.get("relevantFlightParams"))?;
+    // A missing sky id is kept as empty so the caller can decide whether to use it
+    let sky_id = params
+        .get("skyId")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let entity_id = params
+        .get("entityId")
+        .and_then(|v| v.as_str())
+        .filter(|id| !id.is_empty())?;
+    let metro_name = if params.get("flightPlaceType").and_then(|v| v.as_str()) == Some("CITY") {
+        params
+            .get("localizedName")
+            .and_then(|v| v.as_str())
+            .map(|name| name.to_string())
+    } else {
+        None
+    };
+    Some(Location {
+        sky_id: sky_id.to_string(),
+        entity_id: entity_id.to_string(),
+        metro_name,
+        coordinates: suggestion_coordinates(item),
+    })
+}
+
+/// Reads `presentation.coordinates` as decimal degrees
+fn suggestion_coordinates(item: &Value) -> Option<Coordinates> {
+    let coordinates = item.get("presentation")?.get("coordinates")?;
+    Some(Coordinates {
+        latitude: coordinates.get("latitude")?.as_f64()?,
+        longitude: coordinates.get("longitude")?.as_f64()?,
+    })
+}
+
+/// Skyscanner list request parameters. The roundtrip endpoint takes `inDate` and
+/// `outDate`; the one-way endpoint takes a single `date` and nothing about a return.
+fn list_query_params(params: &SearchParams) -> HashMap<&'static str, String> {
+    let date = |date: NaiveDate| date.format("%Y-%m-%d").to_string();
+    let mut query_params = HashMap::new();
+    match params.return_date {
+        Some(return_date) => {
+            query_params.insert("inDate", date(params.departure_date));
+            query_params.insert("outDate", date(return_date));
+        }
+        None => {
+            query_params.insert("date", date(params.departure_date));
+        }
+    }
+    // Locations resolved without a sky id are searched by entity id alone
+    if !params.origin.sky_id.is_empty() {
+        query_params.insert("origin", params.origin.sky_id.clone());
+    }
+    query_params.insert("originId", params.origin.entity_id.clone());
+    if !params.destination.sky_id.is_empty() {
+        query_params.insert("destination", params.destination.sky_id.clone());
+    }
+    query_params.insert("destinationId", params.destination.entity_id.clone());
+    query_params.insert("cabinClass", params.cabin.clone());
+    query_params.insert("adults", params.adults.to_string());
+    query_params.insert("children", params.children.to_string());
+    query_params.insert("infants", params.infants.to_string());
+    query_params.insert("market", params.market.clone());
+    query_params.insert("currency", params.currency.clone());
+    query_params.insert("locale", params.locale.clone());
+    query_params
+}
+
+/// Maps a Skyscanner list response to provider-neutral options, quoting prices in
+/// `default_currency` where an itinerary doesn't name its own
+pub(crate) fn skyscanner_results(
+    response: &ListResponse,
+    default_currency: &str,
+) -> ProviderResults {
+    ProviderResults {
+        options: response
+            .items()
+            .map(|item| parse_flight_option(item, default_currency))
+            .collect(),
+        complete: parse_results_complete(response),
+        total_results: parse_total_results(response),
+    }
+}
+
+/// Reads `context.status`; responses without one are treated as complete
+fn parse_results_complete(response: &ListResponse) -> bool {
+    response
+        .context()
+        .and_then(|c| c.status.as_deref())
+        .map(|status| !status.eq_ignore_ascii_case("incomplete"))
+        .unwrap_or(true)
+}
+
+/// Reads the provider's match count from `context.totalResults` or `itineraries.totalResults`
+fn parse_total_results(response: &ListResponse) -> Option<u64> {
+    response
+        .context()
+        .and_then(|c| c.total_results)
+        .or_else(|| response.itineraries()?.total_results)
+}
+
+pub(crate) fn parse_flight_option(item: &Itinerary, default_currency: &str) -> FlightOption {
+    let leg = item.first_leg();
+    let segments = leg.map_or(&[][..], |leg| &leg.segments[..]);
+    let pricing = item.first_pricing_option();
+    // First marketing carrier of the first leg
+    let airline = leg
+        .and_then(|leg| leg.carriers.as_ref())
+        .and_then(|carriers| carriers.marketing.first())
+        .and_then(|carrier| carrier.name.clone())
+        .unwrap_or_else(|| "Unknown Airline".to_string());
+    let flight_numbers: Vec<String> = segments
+        .iter()
+        .filter_map(|segment| segment.flight_number.clone())
+        .collect();
+    let flight_number = flight_numbers.first().cloned().unwrap_or_default();
+    let connection_airports = leg.map(leg_connections).unwrap_or_default();
+    let segment_cabins = item
+        .legs
+        .iter()
+        .flat_map(|leg| &leg.segments)
+        .filter_map(|segment| segment.cabin_class.as_deref())
+        .map(|cabin| cabin.to_lowercase())
+        .collect();
+    let fare_breakdown = pricing
+        .and_then(|pricing| pricing.fare_breakdown.as_ref())
+        .and_then(|breakdown| {
+            Some(FareBreakdown {
+                base: breakdown.base?,
+                taxes: breakdown.taxes.unwrap_or(0.0),
+                carrier_fees: breakdown.carrier_fees.unwrap_or(0.0),
+            })
+        });
+    // Operating flight number of the first segment, kept only for codeshares
+    let operating_flight_number = segments
+        .first()
+        .and_then(|segment| segment.operating_flight_number.as_deref())
+        .filter(|n| !n.is_empty() && *n != flight_number)
+        .map(|n| n.to_string());
+    let departure = leg
+        .and_then(|leg| leg.departure.clone())
+        .unwrap_or_default();
+    let arrival = leg.and_then(|leg| leg.arrival.clone()).unwrap_or_default();
+    let min_connection_minutes = leg.and_then(|leg| leg.min_connection_time);
+    // Route codes from first leg
+    let leg_place = |place: Option<&Place>| {
+        place
+            .and_then(|place| place.code())
+            .unwrap_or("")
+            .to_string()
+    };
+    let origin_airport = leg_place(leg.and_then(|leg| leg.origin.as_ref()));
+    let destination_airport = leg_place(leg.and_then(|leg| leg.destination.as_ref()));
+    let booking = pricing.and_then(|pricing| pricing.items.first());
+    let booking_url = booking.and_then(|booking| booking.url.clone());
+    let agent_rating = booking
+        .and_then(|booking| booking.rating.as_ref())
+        .map(Rating::value);
+    let duration_minutes = leg.and_then(|leg| leg.duration_in_minutes);
+    let duration = format_duration(duration_minutes);
+    let stops = leg.and_then(|leg| leg.stop_count).unwrap_or(0) as usize;
+    // Price and currency from the first pricing option, else the legacy item price
+    let quoted = pricing.and_then(|pricing| pricing.price.as_ref());
+    let price = quoted
+        .and_then(|p| p.amount)
+        .or_else(|| item.price.as_ref()?.amount)
+        .unwrap_or(0.0);
+    let currency = quoted
+        .and_then(|p| p.currency_code.clone())
+        .or_else(|| item.price.as_ref()?.currency_code.clone())
+        .unwrap_or_else(|| default_currency.to_string());
+    // A round-trip fare covers the time in the air both ways
+    let travel_minutes = item
+        .legs
+        .iter()
+        .map(|leg| leg.duration_in_minutes)
+        .sum::<Option<u64>>();
+    let price_per_hour = price_per_hour(price, travel_minutes);
+    // Self-transfer itineraries are built from separate tickets without connection protection
+    let self_transfer = item.is_self_transfer || item.virtual_interline;
+    let baggage_fee = pricing
+        .and_then(|pricing| pricing.baggage_fee.as_ref())
+        .map(|fee| fee.amount())
+        .filter(|fee| *fee > 0.0);
+    let quote_age_seconds = pricing.and_then(|pricing| pricing.quote_age);
+    let refundable = pricing.and_then(|pricing| pricing.is_refundable);
+    // Roundtrip itineraries carry the inbound journey as a second leg
+    let cabins = leg.map(leg_cabins).unwrap_or_default();
+    let return_leg = item.legs.get(1).map(leg_details);
+    FlightOption {
+        airline,
+        flight_number,
+        flight_numbers,
+        operating_flight_number,
+        segment_count: segments.len(),
+        connection_airports,
+        departure,
+        arrival,
+        origin_airport,
+        destination_airport,
+        booking_url,
+        agent_rating,
+        duration,
+        duration_minutes,
+        min_connection_minutes,
+        segment_cabins,
+        cabins,
+        return_leg,
+        price_confidence: PriceConfidence::Final,
+        stops,
+        price,
+        price_per_hour,
+        price_per_km: None,
+        prices: HashMap::from([(currency.clone(), price)]),
+        currency,
+        self_transfer,
+        baggage_fee,
+        currency_mismatch: false,
+        quote_age_seconds,
+        refundable,
+        fare_breakdown,
+        rank_reason: None,
+    }
+}
+
+fn leg_details(leg: &Leg) -> LegDetails {
+    LegDetails {
+        airline: leg
+            .carriers
+            .as_ref()
+            .and_then(|carriers| carriers.marketing.first())
+            .and_then(|carrier| carrier.name.clone())
+            .unwrap_or_else(|| "Unknown Airline".to_string()),
+        flight_numbers: leg
+            .segments
+            .iter()
+            .filter_map(|segment| segment.flight_number.clone())
+            .collect(),
+        departure: leg.departure.clone().unwrap_or_default(),
+        arrival: leg.arrival.clone().unwrap_or_default(),
+        duration: format_duration(leg.duration_in_minutes),
+        duration_minutes: leg.duration_in_minutes,
+        stops: leg.stop_count.unwrap_or(0) as usize,
+        cabins: leg_cabins(leg),
+        connection_airports: leg_connections(leg),
+    }
+}
+
+/// Connections are the arrival airports of every segment but the last, upper case
+fn leg_connections(leg: &Leg) -> Vec<String> {
+    leg.segments
+        .iter()
+        .take(leg.segments.len().saturating_sub(1))
+        .filter_map(|segment| segment.destination.as_ref()?.code())
+        .map(|code| code.to_uppercase())
+        .collect()
+}
+
+/// Cabin classes of a leg's segments in travel order, without repeats
+fn leg_cabins(leg: &Leg) -> Vec<String> {
+    let mut cabins: Vec<String> = Vec::new();
+    for cabin in leg
+        .segments
+        .iter()
+        .filter_map(|segment| segment.cabin_class.as_deref())
+    {
+        let cabin = cabin.to_lowercase();
+        if !cabins.contains(&cabin) {
+            cabins.push(cabin);
+        }
+    }
+    cabins
+}
+
+/// Guards against zero or unknown durations
+fn price_per_hour(price: f64, duration_minutes: Option<u64>) -> Option<f64> {
+    duration_minutes
+        .filter(|mins| *mins > 0)
+        .map(|mins| price / (mins as f64 / 60.0))
+}
+
+/// Lines to log for an outbound request: a summary at info, plus the full
+/// parameters at the configured level. Key-like parameters are always redacted.
+fn request_log_lines(
+    setting: RequestLogLevel,
+    endpoint: &str,
+    query_params: &HashMap<&str, String>,
+) -> Vec<(Level, String)> {
+    let mut params: Vec<(&str, &str)> = query_params
+        .iter()
+        .map(|(name, value)| {
+            if name.to_lowercase().contains("key") {
+                (*name, "[redacted]")
+            } else {
+                (*name, value.as_str())
+            }
+        })
+        .collect();
+    params.sort();
+    let detail = format!("Calling Skyscanner {} API with: {:?}", endpoint, params);
+    match setting {
+        RequestLogLevel::Info => vec![(Level::INFO, detail)],
+        RequestLogLevel::Debug => vec![
+            (Level::INFO, format!("Calling Skyscanner {} API", endpoint)),
+            (Level::DEBUG, detail),
+        ],
+        RequestLogLevel::Off => vec![(Level::INFO, format!("Calling Skyscanner {} API", endpoint))],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{FLIGHT_STATUS_ERROR, PROVIDER_REQUEST_ERROR, tally};
+
+    fn search_params() -> SearchParams {
+        let location = |sky_id: &str, entity_id: &str| Location {
+            sky_id: sky_id.to_string(),
+            entity_id: entity_id.to_string(),
+            ..Default::default()
+        };
+        SearchParams {
+            trip_type: TripType::RoundTrip,
+            origin: location("AUS", "95673463"),
+            destination: location("BCN", "95565085"),
+            departure_date: NaiveDate::from_ymd_opt(2025, 6, 1).unwrap(),
+            return_date: NaiveDate::from_ymd_opt(2025, 6, 8),
+            cabin: "economy".to_string(),
+            adults: 1,
+            children: 0,
+            infants: 0,
+            market: "US".to_string(),
+            currency: "USD".to_string(),
+            locale: "en-US".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_list_query_params() {
+        let params = search_params();
+        let query_params = list_query_params(&params);
+        assert_eq!(query_params["inDate"], "2025-06-01");
+        assert_eq!(query_params["outDate"], "2025-06-08");
+        assert!(!query_params.contains_key("date"));
+        assert_eq!(query_params["origin"], "AUS");
+        assert_eq!(query_params["destinationId"], "95565085");
+        assert_eq!(query_params["cabinClass"], "economy");
+
+        // One-way searches carry a single date, and entity-only locations no sky id
+        let one_way = SearchParams {
+            trip_type: TripType::OneWay,
+            return_date: None,
+            origin: Location {
+                sky_id: String::new(),
+                ..params.origin.clone()
+            },
+            ..params.shifted(1)
+        };
+        let query_params = list_query_params(&one_way);
+        assert_eq!(query_params["date"], "2025-06-02");
+        assert!(!query_params.contains_key("inDate") && !query_params.contains_key("outDate"));
+        assert!(!query_params.contains_key("origin"));
+        assert_eq!(query_params["originId"], "95673463");
+    }
+
+    #[test]
+    fn test_request_log_lines_respect_level() {
+        let mut query_params = HashMap::new();
+        query_params.insert("origin", "AUS".to_string());
+        query_params.insert("apiKey", "secret-value".to_string());
+
+        let lines = request_log_lines(
+            RequestLogLevel::Info,
+            "flights/roundtrip/list",
+            &query_params,
+        );
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].0, Level::INFO);
+        assert!(lines[0].1.contains("AUS"));
+
+        let lines = request_log_lines(
+            RequestLogLevel::Debug,
+            "flights/roundtrip/list",
+            &query_params,
+        );
+        assert_eq!(
+            lines[0],
+            (
+                Level::INFO,
+                "Calling Skyscanner flights/roundtrip/list API".to_string()
+            )
+        );
+        assert_eq!(lines[1].0, Level::DEBUG);
+
+        let lines = request_log_lines(
+            RequestLogLevel::Off,
+            "flights/roundtrip/list",
+            &query_params,
+        );
+        assert_eq!(lines.len(), 1);
+        assert!(!lines[0].1.contains("AUS"));
+
+        for setting in [RequestLogLevel::Info, RequestLogLevel::Debug] {
+            let lines = request_log_lines(setting, "flights/roundtrip/list", &query_params);
+            assert!(lines.iter().all(|(_, line)| !line.contains("secret-value")));
+        }
+    }
+
+    #[test]
+    fn test_location_without_sky_id() {
+        let suggestion = |sky_id: Option<&str>, entity_id: &str| {
+            let mut params =
+                serde_json::json!({ "entityId": entity_id, "flightPlaceType": "AIRPORT" });
+            if let Some(sky_id) = sky_id {
+                params["skyId"] = serde_json::json!(sky_id);
+            }
+            serde_json::json!({ "navigation": { "relevantFlightParams": params } })
+        };
+        // Entries missing a sky id are skipped in favour of complete ones
+        let data = serde_json::json!({ "inputSuggest": [
+            suggestion(None, "128668889"),
+            suggestion(Some(""), "128668890"),
+            suggestion(Some("BCN"), "95565085")
+        ] });
+        let location = parse_location(&data, "Barcelona", false).unwrap();
+        assert_eq!(
+            (location.sky_id.as_str(), location.entity_id.as_str()),
+            ("BCN", "95565085")
+        );
+        assert_eq!(location.place_id(), "BCN");
+
+        // With only entity ids, fall back to the first one when allowed
+        let data = serde_json::json!({ "inputSuggest": [suggestion(None, "128668889")] });
+        let location = parse_location(&data, "Reus", true).unwrap();
+        assert_eq!(location.sky_id, "");
+        assert_eq!(location.entity_id, "128668889");
+        assert_eq!(location.place_id(), "128668889");
+        assert_eq!(location.coordinates, None);
+        let err = parse_location(&data, "Reus", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid response: No valid airport found in auto-complete response"
+        );
+    }
+
+    #[test]
+    fn test_location_coordinates() {
+        let data = serde_json::json!({ "inputSuggest": [{
+            "presentation": { "coordinates": { "latitude": 41.2974, "longitude": 2.0833 } },
+            "navigation": { "relevantFlightParams": {
+                "skyId": "BCN", "entityId": "95565085", "flightPlaceType": "AIRPORT"
+            } }
+        }] });
+        let location = parse_location(&data, "BCN", false).unwrap();
+        assert_eq!(
+            location.coordinates,
+            Some(Coordinates {
+                latitude: 41.2974,
+                longitude: 2.0833
+            })
+        );
+    }
+
+    #[test]
+    fn test_trip_type_parse() {
+        assert_eq!(TripType::parse(None, true).unwrap(), TripType::RoundTrip);
+        assert_eq!(TripType::parse(None, false).unwrap(), TripType::OneWay);
+        assert_eq!(
+            TripType::parse(Some("ONE_WAY"), false).unwrap(),
+            TripType::OneWay
+        );
+        assert_eq!(
+            TripType::parse(Some("round_trip"), false).unwrap(),
+            TripType::RoundTrip
+        );
+        let err = TripType::parse(Some("multi_city"), true).unwrap_err();
+        assert_eq!(err.code(), "ERR_INVALID_ARGUMENT");
+    }
+
+    #[test]
+    fn test_http_client_is_shared() {
+        assert!(std::ptr::eq(http_client(), http_client()));
+    }
+
+    #[test]
+    fn test_configured_api_host() {
+        let config = Config {
+            skyscanner_api_host: "flights.example.com".to_string(),
+            ..Default::default()
+        };
+        let provider = SkyscannerProvider::from_config(&config);
+        let headers = provider
+            .headers("test-key", "flights/auto-complete", &())
+            .unwrap();
+        assert_eq!(headers["X-RapidAPI-Host"], "flights.example.com");
+        assert_eq!(headers["X-RapidAPI-Key"], "test-key");
+
+        let default = SkyscannerProvider::from_config(&Config::default());
+        assert_eq!(
+            default
+                .headers("test-key", "flights/auto-complete", &())
+                .unwrap()["X-RapidAPI-Host"],
+            "skyscanner89.p.rapidapi.com"
+        );
+        assert_eq!(default.base_url, "https://skyscanner89.p.rapidapi.com");
+    }
+
+    #[test]
+    fn test_cache_key_header() {
+        let config = Config {
+            cache_key_header: Some("X-Cache-Key".to_string()),
+            ..Default::default()
+        };
+        let provider = SkyscannerProvider::from_config(&config);
+        let endpoint = "flights/one-way/list";
+        let params = |pairs: &[(&'static str, &str)]| -> HashMap<&'static str, String> {
+            pairs.iter().map(|(k, v)| (*k, v.to_string())).collect()
+        };
+        let key = |query: &HashMap<&'static str, String>| {
+            provider.headers("test-key", endpoint, query).unwrap()["X-Cache-Key"].clone()
+        };
+        let search = params(&[("originId", "95673829"), ("date", "2025-06-01")]);
+        let reordered = params(&[("date", "2025-06-01"), ("originId", "95673829")]);
+        let other_date = params(&[("originId", "95673829"), ("date", "2025-06-02")]);
+        assert_eq!(key(&search), key(&reordered));
+        assert_ne!(key(&search), key(&other_date));
+
+        let unset = SkyscannerProvider::from_config(&Config::default());
+        let headers = unset.headers("test-key", endpoint, &search).unwrap();
+        assert!(!headers.contains_key("X-Cache-Key"));
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(async {
+            // Accepts connections but never answers
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (_socket, _) = listener.accept().await.unwrap();
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            });
+            let provider = SkyscannerProvider {
+                api_key: Some("test-key".to_string()),
+                timeout: Duration::from_millis(200),
+                base_url: format!("http://{}", address),
+                ..SkyscannerProvider::from_config(&Config::default())
+            };
+            provider
+                .fetch_flight_list(&search_params(), &RequestBudget::new(10))
+                .await
+        });
+        let err = result.unwrap_err();
+        assert_eq!(err.code(), "ERR_TIMEOUT");
+    }
+
+    #[test]
+    fn test_failed_list_request_counted_once() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        tally::reset();
+        let (result, attempts) = runtime.block_on(async {
+            let server = MockServer::start().await;
+            Mock::given(method("GET"))
+                .and(path("/flights/roundtrip/list"))
+                .respond_with(ResponseTemplate::new(500))
+                .mount(&server)
+                .await;
+            let provider = SkyscannerProvider::from_config(&Config {
+                rapidapi_key: Some("test-key".to_string()),
+                skyscanner_base_url: server.uri(),
+                max_retries: 2,
+                retry_base_delay_ms: 1,
+                ..Config::default()
+            });
+            let result = provider
+                .fetch_flight_list(&search_params(), &RequestBudget::new(10))
+                .await;
+            (result, server.received_requests().await.unwrap().len())
+        });
+        assert_eq!(result.unwrap_err().code(), "ERR_API");
+        // Retried attempts make up one failed request
+        assert_eq!(attempts, 3);
+        assert_eq!(tally::count(PROVIDER_REQUEST_ERROR), 1);
+        assert_eq!(tally::count(FLIGHT_STATUS_ERROR), 0);
+    }
+
+    #[test]
+    fn test_retry_budget_stops_before_max_retries() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            budget: Duration::from_secs(10),
+            jitter: false,
+        };
+        // Long Retry-After values use up the budget after two retries
+        let mut retries = RetryState::new(policy);
+        let retry_after = Some(Duration::from_secs(4));
+        assert_eq!(
+            retries.next_delay(retry_after),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(
+            retries.next_delay(retry_after),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(retries.next_delay(retry_after), None);
+
+        // Without Retry-After the backoff doubles until max retries
+        let mut retries = RetryState::new(RetryPolicy {
+            max_retries: 2,
+            ..policy
+        });
+        assert_eq!(retries.next_delay(None), Some(Duration::from_millis(500)));
+        assert_eq!(retries.next_delay(None), Some(Duration::from_millis(1000)));
+        assert_eq!(retries.next_delay(None), None);
+    }
+
+    #[test]
+    fn test_retry_backoff_jitter() {
+        let mut retries = RetryState::new(RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(400),
+            budget: Duration::from_secs(10),
+            jitter: true,
+        });
+        let first = retries.next_delay(None).unwrap();
+        assert!(first >= Duration::from_millis(400) && first < Duration::from_millis(600));
+        let second = retries.next_delay(None).unwrap();
+        assert!(second >= Duration::from_millis(800) && second < Duration::from_millis(1200));
+        // Retry-After is honoured exactly
+        assert_eq!(
+            retries.next_delay(Some(Duration::from_secs(2))),
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn test_not_found_means_no_results() {
+        assert!(not_found_means_no_results(
+            r#"{"message": "No flights found for this route"}"#
+        ));
+        assert!(not_found_means_no_results(""));
+        assert!(!not_found_means_no_results(
+            r#"{"message": "Endpoint '/flights/roundtrip/lst' does not exist"}"#
+        ));
+    }
+
+    #[test]
+    fn test_status_error_flags_rate_limits() {
+        let error = status_error(
+            reqwest::StatusCode::TOO_MANY_REQUESTS,
+            "slow down".to_string(),
+        );
+        assert_eq!(error.code(), "ERR_RATE_LIMITED");
+        let error = status_error(reqwest::StatusCode::BAD_GATEWAY, "bad gateway".to_string());
+        assert_eq!(error.code(), "ERR_API");
+    }
+}
@@ -0,0 +1,97 @@
+//! Replays captured Skyscanner list responses through the parser so refactors
+//! can't silently stop extracting fields from a known response shape.
+
+use flight_agent::flight_search_tool::{FlightOption, parse_response};
+use serde_json::Value;
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+const FIXTURE_DIR: &str = "tests/fixtures";
+/// Share of checked fields that must be extracted across all fixtures
+const MIN_FIELD_SUCCESS_RATE: f64 = 0.95;
+
+/// (fields extracted, fields checked) for one option
+fn extracted_fields(option: &FlightOption) -> (usize, usize) {
+    let checks = [
+        option.airline != "Unknown Airline",
+        !option.flight_number.is_empty(),
+        !option.departure.is_empty(),
+        !option.arrival.is_empty(),
+        option.duration_minutes.is_some(),
+        option.price > 0.0,
+        !option.currency.is_empty(),
+    ];
+    (checks.iter().filter(|ok| **ok).count(), checks.len())
+}
+
+#[test]
+fn test_replay_fixtures() {
+    let mut fixtures: Vec<_> =
+        fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE_DIR))
+            .expect("fixture directory is readable")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures found in {}", FIXTURE_DIR);
+
+    let (mut extracted, mut checked) = (0, 0);
+    for path in &fixtures {
+        let text = fs::read_to_string(path).unwrap();
+        let data: Value = serde_json::from_str(&text)
+            .unwrap_or_else(|e| panic!("{} is not valid JSON: {}", path.display(), e));
+        let result = panic::catch_unwind(|| parse_response(&data, "USD"))
+            .unwrap_or_else(|_| panic!("parser panicked on {}", path.display()))
+            .unwrap_or_else(|e| panic!("{} doesn't match the schema: {}", path.display(), e));
+        for option in &result.options {
+            let (ok, total) = extracted_fields(option);
+            extracted += ok;
+            checked += total;
+        }
+    }
+
+    assert!(checked > 0, "fixtures produced no options");
+    let rate = extracted as f64 / checked as f64;
+    assert!(
+        rate >= MIN_FIELD_SUCCESS_RATE,
+        "extracted {} of {} fields ({:.1}%), below the {:.0}% minimum",
+        extracted,
+        checked,
+        rate * 100.0,
+        MIN_FIELD_SUCCESS_RATE * 100.0
+    );
+}
+
+#[test]
+fn test_fixture_shapes() {
+    let load = |name: &str| -> Value {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(FIXTURE_DIR)
+            .join(name);
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    };
+
+    let result = parse_response(&load("roundtrip_list_complete.json"), "USD").unwrap();
+    assert_eq!(result.options.len(), 2);
+    assert!(result.results_complete);
+    assert_eq!(result.total_results, Some(2));
+
+    let result = parse_response(&load("roundtrip_list_wrapped_incomplete.json"), "USD").unwrap();
+    assert_eq!(result.options.len(), 1);
+    assert!(!result.results_complete);
+
+    let result = parse_response(&load("roundtrip_list_legacy_price.json"), "USD").unwrap();
+    assert_eq!(result.options[0].price, 889.0);
+    assert_eq!(result.options[0].currency, "EUR");
+
+    let result = parse_response(&load("roundtrip_list_codeshare_extras.json"), "USD").unwrap();
+    assert_eq!(
+        result.options[0].operating_flight_number.as_deref(),
+        Some("BA196")
+    );
+    assert!(result.options[1].self_transfer);
+
+    let result = parse_response(&load("roundtrip_list_no_results.json"), "USD").unwrap();
+    assert!(result.options.is_empty());
+}
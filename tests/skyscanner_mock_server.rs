@@ -0,0 +1,202 @@
+//! Runs whole searches through `SkyscannerProvider` against a local mock of the
+//! RapidAPI endpoints, covering the HTTP, parsing and formatting path end to end.
+
+use flight_agent::config::Config;
+use flight_agent::error::FlightSearchError;
+use flight_agent::flight_search_tool::{FlightSearchArgs, FlightSearchTool};
+use flight_agent::provider::SkyscannerProvider;
+use rig::tool::Tool;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn fixture(name: &str) -> Value {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn suggestion(sky_id: &str, entity_id: &str) -> Value {
+    json!({ "inputSuggest": [{ "navigation": { "relevantFlightParams": {
+        "skyId": sky_id, "entityId": entity_id, "flightPlaceType": "AIRPORT"
+    } } }] })
+}
+
+/// Mock server answering auto-complete for AUS and BCN and the roundtrip list with `list`
+async fn mock_skyscanner(list: Value) -> MockServer {
+    mock_skyscanner_response(ResponseTemplate::new(200).set_body_json(list)).await
+}
+
+async fn mock_skyscanner_response(list: ResponseTemplate) -> MockServer {
+    let server = MockServer::start().await;
+    for (sky_id, entity_id) in [("AUS", "95673829"), ("BCN", "95565085")] {
+        Mock::given(method("GET"))
+            .and(path("/flights/auto-complete"))
+            .and(query_param("query", sky_id))
+            .respond_with(ResponseTemplate::new(200).set_body_json(suggestion(sky_id, entity_id)))
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .and(path("/flights/roundtrip/list"))
+        .and(header("X-RapidAPI-Key", "test-key"))
+        .and(query_param("originId", "95673829"))
+        .and(query_param("destinationId", "95565085"))
+        .respond_with(list)
+        .mount(&server)
+        .await;
+    server
+}
+
+fn test_config(server: &MockServer) -> Config {
+    Config {
+        rapidapi_key: Some("test-key".to_string()),
+        skyscanner_base_url: server.uri(),
+        ..Default::default()
+    }
+}
+
+async fn search(server: &MockServer) -> String {
+    search_with(test_config(server)).await.unwrap()
+}
+
+async fn search_with(config: Config) -> Result<String, FlightSearchError> {
+    let provider = SkyscannerProvider::from_config(&config);
+    let tool = FlightSearchTool::with_provider(Box::new(provider), config);
+    let args: FlightSearchArgs = serde_json::from_value(json!({
+        "source": "AUS",
+        "destination": "BCN",
+        "trip_type": "round_trip"
+    }))
+    .unwrap();
+    tool.call(args).await
+}
+
+#[tokio::test]
+async fn test_unwrapped_list_response() {
+    let server = mock_skyscanner(fixture("roundtrip_list_complete.json")).await;
+    let output = search(&server).await;
+    assert!(output.contains("Here are some flight options:"));
+    // Cheapest first
+    assert!(output.contains("1. **Airline**: United"));
+    assert!(output.contains("548.00 USD"));
+    assert!(output.contains("2. **Airline**: British Airways"));
+    assert!(output.contains("**Flight Numbers**: BA196 → BA478"));
+    assert!(output.contains("612.40 USD"));
+}
+
+#[tokio::test]
+async fn test_wrapped_list_response() {
+    let server = mock_skyscanner(fixture("roundtrip_list_wrapped_incomplete.json")).await;
+    let output = search(&server).await;
+    assert!(output.contains("1. **Airline**: Delta"));
+    assert!(output.contains("701.20 USD"));
+    assert_eq!(output.matches("**Airline**").count(), 1);
+}
+
+#[tokio::test]
+async fn test_not_found_as_empty_results() {
+    let not_found =
+        ResponseTemplate::new(404).set_body_json(json!({ "message": "No flights found" }));
+    let server = mock_skyscanner_response(not_found).await;
+    let config = Config {
+        skyscanner_not_found_as_empty: true,
+        ..test_config(&server)
+    };
+    let output = search_with(config).await.unwrap();
+    assert_eq!(output, "No flights found for the given criteria.");
+
+    // Without the policy a 404 is still an API error
+    let err = search_with(test_config(&server)).await.unwrap_err();
+    assert_eq!(err.code(), "ERR_API");
+}
+
+#[tokio::test]
+async fn test_paging_through_results() {
+    let items: Vec<Value> = (1..=5)
+        .map(|i| {
+            json!({
+                "legs": [{
+                    "carriers": { "marketing": [{ "name": "Iberia" }] },
+                    "segments": [{ "flightNumber": format!("IB{}", i) }],
+                    "departure": "2025-06-01T10:00:00",
+                    "arrival": "2025-06-01T22:30:00",
+                    "durationInMinutes": 750,
+                    "stopCount": 0
+                }],
+                "pricingOptions": [{ "price": { "amount": 400.0 + i as f64, "currencyCode": "USD" } }]
+            })
+        })
+        .collect();
+    let server =
+        mock_skyscanner(json!({ "itineraries": { "buckets": [{ "items": items }] } })).await;
+    let config = test_config(&server);
+    let provider = SkyscannerProvider::from_config(&config);
+    let tool = FlightSearchTool::with_provider(Box::new(provider), config);
+    let args = || -> FlightSearchArgs {
+        serde_json::from_value(json!({
+            "source": "AUS",
+            "destination": "BCN",
+            "trip_type": "round_trip"
+        }))
+        .unwrap()
+    };
+
+    let mut pages = Vec::new();
+    for page in 1..=3 {
+        let page = tool.search_page(args(), page, 2).await.unwrap();
+        assert_eq!(page.total, 5);
+        pages.push(
+            page.results
+                .into_iter()
+                .map(|option| option.flight_number)
+                .collect::<Vec<_>>(),
+        );
+    }
+    assert_eq!(
+        pages,
+        vec![vec!["IB1", "IB2"], vec!["IB3", "IB4"], vec!["IB5"]]
+    );
+    assert!(
+        tool.search_page(args(), 4, 2)
+            .await
+            .unwrap()
+            .results
+            .is_empty()
+    );
+    let err = tool.search_page(args(), 0, 2).await.unwrap_err();
+    assert_eq!(err.code(), "ERR_INVALID_ARGUMENT");
+
+    // Later pages come from the cached result set, not another list request
+    let requests = server.received_requests().await.unwrap();
+    let list_requests = requests
+        .iter()
+        .filter(|request| request.url.path() == "/flights/roundtrip/list")
+        .count();
+    assert_eq!(list_requests, 1);
+}
+
+#[tokio::test]
+async fn test_retries_stop_at_request_cap() {
+    let server = mock_skyscanner_response(ResponseTemplate::new(429)).await;
+    let config = Config {
+        max_requests_per_search: 3,
+        max_retries: 5,
+        retry_base_delay_ms: 1,
+        ..test_config(&server)
+    };
+    let err = search_with(config).await.unwrap_err();
+    assert!(matches!(err, FlightSearchError::RequestLimitExceeded(3)));
+
+    // Two auto-completes leave room for one list attempt and no retries
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3);
+    let list_requests = requests
+        .iter()
+        .filter(|request| request.url.path() == "/flights/roundtrip/list")
+        .count();
+    assert_eq!(list_requests, 1);
+}